@@ -0,0 +1,229 @@
+//! basis analytics for cypher futures, from the decoded group and price history accounts.
+//!
+//! prices are in native quote per native c asset, relative values are signed basis points.
+use {
+    crate::{constants::*, CypherGroup, CypherMarket, PriceHistory},
+    jet_proto_math::Number,
+    std::convert::TryFrom,
+};
+
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// gets `value` relative to `reference` in signed basis points, `None` if the reference is zero or the result does
+/// not fit in an `i64`
+fn get_relative_bps(value: Number, reference: Number) -> Option<i64> {
+    if reference == Number::ZERO {
+        return None;
+    }
+    let (diff, is_negative) = if value >= reference {
+        (value - reference, false)
+    } else {
+        (reference - value, true)
+    };
+    let bps = diff * 10_000_u64 / reference;
+    if bps > Number::from(i64::MAX as u64) {
+        return None;
+    }
+    let bps = i64::try_from(bps.as_u64(0)).ok()?;
+    Some(if is_negative { -bps } else { bps })
+}
+
+/// the basis of a future against its oracle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Basis {
+    pub market_price: u64,
+    pub oracle_price: u64,
+    /// the market price minus the oracle price
+    pub basis: i128,
+    /// the basis relative to the oracle price
+    pub basis_bps: i64,
+    /// the basis relative to the oracle price, annualized over the time left until expiry,
+    /// `None` if the market has no expiry, it has already passed or the annualized basis does not fit in an `i64`
+    pub annualized_basis_bps: Option<i64>,
+}
+
+impl Basis {
+    /// gets the basis of the market at the given unix timestamp,
+    /// `None` if the oracle price is zero or the basis in basis points does not fit in an `i64`
+    pub fn new(market: &CypherMarket, now: u64) -> Option<Self> {
+        let market_price = market.market_price;
+        let oracle_price = market.oracle_price();
+        let basis_bps = get_relative_bps(Number::from(market_price), Number::from(oracle_price))?;
+        let annualized_basis_bps = market.time_to_expiry(now).and_then(|time_to_expiry| {
+            i64::try_from(basis_bps as i128 * SECONDS_PER_YEAR as i128 / time_to_expiry as i128)
+                .ok()
+        });
+        Some(Self {
+            market_price,
+            oracle_price,
+            basis: market_price as i128 - oracle_price as i128,
+            basis_bps,
+            annualized_basis_bps,
+        })
+    }
+}
+
+/// the divergence of the price history's twaps from the oracle price
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwapDivergence {
+    pub oracle_price: u64,
+    pub longer_twap: Option<Number>,
+    pub shorter_twap: Option<Number>,
+    /// the longer twap relative to the oracle price
+    pub longer_divergence_bps: Option<i64>,
+    /// the shorter twap relative to the oracle price
+    pub shorter_divergence_bps: Option<i64>,
+}
+
+/// gets the divergence of the twaps of the given market's price history from its oracle price
+///
+/// `None` if the market is not listed or the price history belongs to another market
+pub fn get_twap_divergence(
+    group: &CypherGroup,
+    market_idx: usize,
+    price_history: &PriceHistory,
+) -> Option<TwapDivergence> {
+    let market = group.get_cypher_market(market_idx)?;
+    let c_asset_mint = group.get_cypher_token(market_idx)?.mint;
+    if price_history.c_asset_mint != c_asset_mint {
+        return None;
+    }
+    let oracle_price = market.oracle_price();
+    let longer_twap = price_history.longer_twap();
+    let shorter_twap = price_history.shorter_twap();
+    Some(TwapDivergence {
+        oracle_price,
+        longer_twap,
+        shorter_twap,
+        longer_divergence_bps: longer_twap
+            .and_then(|twap| get_relative_bps(twap, Number::from(oracle_price))),
+        shorter_divergence_bps: shorter_twap
+            .and_then(|twap| get_relative_bps(twap, Number::from(oracle_price))),
+    })
+}
+
+/// the basis and twap divergence of a market
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketBasis {
+    pub market_idx: usize,
+    pub basis: Basis,
+    /// `None` if no price history was given for the market
+    pub twap_divergence: Option<TwapDivergence>,
+}
+
+/// gets the basis of every listed market with a non-zero oracle price, along with its twap divergence
+/// if its price history is among the given ones
+pub fn get_market_bases<'a>(
+    group: &CypherGroup,
+    price_histories: impl IntoIterator<Item = &'a PriceHistory> + Clone,
+    now: u64,
+) -> Vec<MarketBasis> {
+    (0..MARKETS_MAX_CNT)
+        .filter_map(|market_idx| {
+            let basis = Basis::new(group.get_cypher_market(market_idx)?, now)?;
+            let twap_divergence = price_histories
+                .clone()
+                .into_iter()
+                .find_map(|history| get_twap_divergence(group, market_idx, history));
+            Some(MarketBasis {
+                market_idx,
+                basis,
+                twap_divergence,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{test_utils::*, MarketType},
+        anchor_lang::prelude::Pubkey,
+    };
+
+    const NOW: u64 = 1_000;
+
+    fn group(market_price: u64, oracle_price: u64, expires_at: u64) -> Box<CypherGroup> {
+        CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, oracle_price)
+            .market_price(0, market_price)
+            .expiry(0, MarketType::PairFuture, expires_at)
+            .build()
+    }
+
+    fn basis(market_price: u64, oracle_price: u64, expires_at: u64) -> Option<Basis> {
+        Basis::new(
+            group(market_price, oracle_price, expires_at)
+                .get_cypher_market(0)
+                .unwrap(),
+            NOW,
+        )
+    }
+
+    #[test]
+    fn basis_and_annualized_basis() {
+        let half_year = NOW + SECONDS_PER_YEAR / 2;
+        assert_eq!(
+            basis(42, 40, half_year),
+            Some(Basis {
+                market_price: 42,
+                oracle_price: 40,
+                basis: 2,
+                basis_bps: 500,
+                annualized_basis_bps: Some(1_000),
+            })
+        );
+        let discount = basis(38, 40, half_year).unwrap();
+        assert_eq!((discount.basis, discount.basis_bps), (-2, -500));
+        assert_eq!(discount.annualized_basis_bps, Some(-1_000));
+
+        // no expiry, or an expiry which has passed
+        assert_eq!(basis(42, 40, 0).unwrap().annualized_basis_bps, None);
+        assert_eq!(basis(42, 40, NOW).unwrap().annualized_basis_bps, None);
+    }
+
+    #[test]
+    fn basis_without_oracle_price_or_overflowing() {
+        assert_eq!(basis(42, 0, 0), None);
+        assert_eq!(basis(u64::MAX, 1, 0), None);
+        // a one second expiry annualizes the basis beyond an i64
+        let basis = basis(100_000_000, 1, NOW + 1).unwrap();
+        assert_eq!(basis.annualized_basis_bps, None);
+    }
+
+    #[test]
+    fn twap_divergence_over_wrapped_buffer() {
+        let group = group(40, 40, 0);
+        let c_asset_mint = group.get_cypher_token(0).unwrap().mint;
+        let mut history = price_history(group.self_address, c_asset_mint);
+        // a price left over from before the window, then a window wrapping around the end of the buffer
+        record_price(&mut history, 2, 1_000, 1);
+        record_price(&mut history, PRICE_HISTORY_SIZE - 2, 38, 2);
+        record_price(&mut history, PRICE_HISTORY_SIZE - 1, 40, 3);
+        record_price(&mut history, 0, 42, 4);
+        record_price(&mut history, 1, 44, 5);
+        history.longer_head = (PRICE_HISTORY_SIZE - 2) as u16;
+        history.shorter_head = 0;
+
+        assert_eq!(
+            history.get_window_twap((PRICE_HISTORY_SIZE - 2) as u16),
+            Some(Number::from(41_u64))
+        );
+        assert_eq!(history.get_window_twap(1), Some(Number::from(44_u64)));
+        assert_eq!(
+            get_twap_divergence(&group, 0, &history),
+            Some(TwapDivergence {
+                oracle_price: 40,
+                longer_twap: Some(Number::from(41_u64)),
+                shorter_twap: Some(Number::from(43_u64)),
+                longer_divergence_bps: Some(250),
+                shorter_divergence_bps: Some(750),
+            })
+        );
+
+        let other = price_history(group.self_address, Pubkey::new_unique());
+        assert_eq!(get_twap_divergence(&group, 0, &other), None);
+        assert_eq!(get_twap_divergence(&group, 1, &history), None);
+    }
+}
@@ -0,0 +1,313 @@
+//! diffs between two versions of a bids or asks `Slab`, to build an incremental book feed.
+//!
+//! prices and quantities are in lots, as stored in the slab.
+use {
+    crate::{
+        client::ToPubkey,
+        serum_slab::{LeafNode, Slab},
+    },
+    anchor_lang::prelude::*,
+    std::collections::{BTreeMap, HashMap, HashSet},
+};
+
+/// a change to a single resting order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L3Event {
+    /// an order was added to the book
+    Added {
+        order_id: u128,
+        price: u64,
+        quantity: u64,
+        /// the open orders account of the order
+        owner: Pubkey,
+        owner_slot: u8,
+        client_order_id: u64,
+    },
+    /// an order was removed from the book, either filled or cancelled
+    Removed {
+        order_id: u128,
+        price: u64,
+        /// the quantity the order had before it was removed
+        quantity: u64,
+    },
+    /// an order was partially filled
+    Reduced {
+        order_id: u128,
+        price: u64,
+        old_quantity: u64,
+        new_quantity: u64,
+    },
+}
+
+impl L3Event {
+    pub fn order_id(&self) -> u128 {
+        match *self {
+            L3Event::Added { order_id, .. }
+            | L3Event::Removed { order_id, .. }
+            | L3Event::Reduced { order_id, .. } => order_id,
+        }
+    }
+}
+
+/// a change to the total quantity at a price level, a new quantity of zero means the level was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L2Change {
+    pub price: u64,
+    pub old_quantity: u64,
+    pub new_quantity: u64,
+}
+
+/// the changes between two versions of the same side of the book
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookDiff {
+    /// removals and reductions in the old book's priority order, followed by additions in the new book's priority order
+    pub l3: Vec<L3Event>,
+    /// the changed levels, best price first
+    pub l2: Vec<L2Change>,
+}
+
+impl BookDiff {
+    pub fn is_empty(&self) -> bool {
+        self.l3.is_empty() && self.l2.is_empty()
+    }
+}
+
+fn get_levels(orders: &[&LeafNode]) -> BTreeMap<u64, u64> {
+    let mut levels = BTreeMap::new();
+    for order in orders {
+        *levels.entry(u64::from(order.price())).or_insert(0) += order.quantity();
+    }
+    levels
+}
+
+/// diffs two versions of the same side of the book
+///
+/// orders are keyed by `order_id`, an order whose quantity grew is reported as removed and added again
+pub fn diff_slabs(old: &Slab, new: &Slab, is_asks: bool) -> BookDiff {
+    let old_orders = old.get_orders(is_asks);
+    let new_orders = new.get_orders(is_asks);
+    let new_by_id: HashMap<u128, &LeafNode> =
+        new_orders.iter().map(|o| (o.order_id(), *o)).collect();
+    let mut readded = HashSet::new();
+    let mut l3 = Vec::new();
+
+    for order in old_orders.iter() {
+        let order_id = order.order_id();
+        let price = u64::from(order.price());
+        let old_quantity = order.quantity();
+        match new_by_id.get(&order_id) {
+            None => l3.push(L3Event::Removed {
+                order_id,
+                price,
+                quantity: old_quantity,
+            }),
+            Some(new_order) if new_order.quantity() < old_quantity => l3.push(L3Event::Reduced {
+                order_id,
+                price,
+                old_quantity,
+                new_quantity: new_order.quantity(),
+            }),
+            Some(new_order) if new_order.quantity() > old_quantity => {
+                l3.push(L3Event::Removed {
+                    order_id,
+                    price,
+                    quantity: old_quantity,
+                });
+                readded.insert(order_id);
+            }
+            Some(_) => (),
+        }
+    }
+
+    let old_ids: HashSet<u128> = old_orders.iter().map(|o| o.order_id()).collect();
+    for order in new_orders.iter() {
+        let order_id = order.order_id();
+        if old_ids.contains(&order_id) && !readded.contains(&order_id) {
+            continue;
+        }
+        l3.push(L3Event::Added {
+            order_id,
+            price: u64::from(order.price()),
+            quantity: order.quantity(),
+            owner: order.owner().to_pubkey(),
+            owner_slot: order.owner_slot(),
+            client_order_id: order.client_order_id(),
+        });
+    }
+
+    BookDiff {
+        l3,
+        l2: diff_levels(&get_levels(&old_orders), &get_levels(&new_orders), is_asks),
+    }
+}
+
+fn diff_levels(old: &BTreeMap<u64, u64>, new: &BTreeMap<u64, u64>, is_asks: bool) -> Vec<L2Change> {
+    let mut prices: Vec<u64> = old.keys().chain(new.keys()).copied().collect();
+    prices.sort_unstable();
+    prices.dedup();
+    if !is_asks {
+        prices.reverse();
+    }
+    prices
+        .into_iter()
+        .filter_map(|price| {
+            let old_quantity = old.get(&price).copied().unwrap_or(0);
+            let new_quantity = new.get(&price).copied().unwrap_or(0);
+            if old_quantity == new_quantity {
+                None
+            } else {
+                Some(L2Change {
+                    price,
+                    old_quantity,
+                    new_quantity,
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*};
+
+    fn diff(old: &SlabBuilder, new: &SlabBuilder, is_asks: bool) -> BookDiff {
+        diff_slabs(
+            Slab::from_bytes(&old.build()),
+            Slab::from_bytes(&new.build()),
+            is_asks,
+        )
+    }
+
+    #[test]
+    fn diffs_bids() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let old = SlabBuilder::bids()
+            .order(1, 100, 10, &alice)
+            .order(2, 100, 5, &bob)
+            .order(3, 99, 7, &alice)
+            .order(4, 98, 3, &bob);
+        let new = SlabBuilder::bids()
+            .order(1, 100, 4, &alice)
+            .order(3, 99, 9, &alice)
+            .order(4, 98, 3, &bob)
+            .order(5, 101, 2, &bob);
+
+        let diff = diff(&old, &new, false);
+        assert_eq!(
+            diff.l3,
+            vec![
+                L3Event::Reduced {
+                    order_id: order_id(100, 1, false),
+                    price: 100,
+                    old_quantity: 10,
+                    new_quantity: 4,
+                },
+                L3Event::Removed {
+                    order_id: order_id(100, 2, false),
+                    price: 100,
+                    quantity: 5,
+                },
+                // the quantity grew, so the order is removed and added again
+                L3Event::Removed {
+                    order_id: order_id(99, 3, false),
+                    price: 99,
+                    quantity: 7,
+                },
+                L3Event::Added {
+                    order_id: order_id(101, 5, false),
+                    price: 101,
+                    quantity: 2,
+                    owner: bob,
+                    owner_slot: 0,
+                    client_order_id: 5,
+                },
+                L3Event::Added {
+                    order_id: order_id(99, 3, false),
+                    price: 99,
+                    quantity: 9,
+                    owner: alice,
+                    owner_slot: 0,
+                    client_order_id: 3,
+                },
+            ]
+        );
+        assert_eq!(
+            diff.l2,
+            vec![
+                L2Change {
+                    price: 101,
+                    old_quantity: 0,
+                    new_quantity: 2,
+                },
+                L2Change {
+                    price: 100,
+                    old_quantity: 15,
+                    new_quantity: 4,
+                },
+                L2Change {
+                    price: 99,
+                    old_quantity: 7,
+                    new_quantity: 9,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_asks() {
+        let owner = Pubkey::new_unique();
+        let old = SlabBuilder::asks()
+            .order(1, 105, 5, &owner)
+            .order(2, 110, 5, &owner);
+        let new = SlabBuilder::asks()
+            .order(2, 110, 5, &owner)
+            .order(3, 103, 1, &owner);
+
+        let diff = diff(&old, &new, true);
+        assert_eq!(
+            diff.l3,
+            vec![
+                L3Event::Removed {
+                    order_id: order_id(105, 1, true),
+                    price: 105,
+                    quantity: 5,
+                },
+                L3Event::Added {
+                    order_id: order_id(103, 3, true),
+                    price: 103,
+                    quantity: 1,
+                    owner,
+                    owner_slot: 0,
+                    client_order_id: 3,
+                },
+            ]
+        );
+        assert_eq!(
+            diff.l2,
+            vec![
+                L2Change {
+                    price: 103,
+                    old_quantity: 0,
+                    new_quantity: 1,
+                },
+                L2Change {
+                    price: 105,
+                    old_quantity: 5,
+                    new_quantity: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_and_empty_books_have_no_diff() {
+        let owner = Pubkey::new_unique();
+        let book = SlabBuilder::asks().order(1, 105, 5, &owner);
+        assert!(diff(&book, &book, true).is_empty());
+        assert!(diff(&SlabBuilder::bids(), &SlabBuilder::bids(), false).is_empty());
+
+        let diff = diff(&SlabBuilder::asks(), &book, true);
+        assert_eq!(diff.l3.len(), 1);
+        assert_eq!(diff.l3[0].order_id(), order_id(105, 1, true));
+    }
+}
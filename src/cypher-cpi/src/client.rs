@@ -1,13 +1,18 @@
 #![allow(dead_code)]
 
 use {
-    crate::accounts::{
-        CloseCypherUser, CloseMarket, CreateCypherUser, DepositCollateral, ExecuteMarket,
-        InitCypherUser, LiquidateCollateral, NoOpCancelOrder as CancelOrder,
-        NoOpCancelOrderDex as CancelOrderDex, NoOpCloseOpenOrders as CloseOpenOrders,
-        NoOpInitOpenOrders as InitOpenOrders, NoOpNewOrderV3 as NewOrderV3,
-        NoOpNewOrderV3Dex as NewOrderV3Dex, NoOpSettleFunds as SettleFunds,
-        NoOpSettleFundsDex as SettleFundsDex, SetDelegate, SettlePosition, WithdrawCollateral,
+    crate::{
+        accounts::{
+            CloseCypherUser, CloseMarket, CreateCypherUser, DepositCollateral, ExecuteMarket,
+            InitCypherUser, LiquidateCollateral, NoOpCancelOrder as CancelOrder,
+            NoOpCancelOrderDex as CancelOrderDex, NoOpCloseOpenOrders as CloseOpenOrders,
+            NoOpInitOpenOrders as InitOpenOrders, NoOpNewOrderV3 as NewOrderV3,
+            NoOpNewOrderV3Dex as NewOrderV3Dex, NoOpSettleFunds as SettleFunds,
+            NoOpSettleFundsDex as SettleFundsDex, SetDelegate, SettlePosition,
+            UpdateMarketExpirationTime, WithdrawCollateral,
+        },
+        ix_accounts::find_ix_accounts,
+        signer::{SignerError, UserAction, UserSigner},
     },
     anchor_discriminator::get_ix_data,
     anchor_lang::{
@@ -30,6 +35,31 @@ impl ToPubkey for [u64; 4] {
     }
 }
 
+/// overrides the signer flag of an account of an instruction built by this module,
+/// e.g. to sign for a pda with `invoke_signed` where the idl does not require a signature
+///
+/// the `user_signer` of the builders taking a [`UserSigner`] is already flagged when it is a pda
+pub trait WithSigner: Sized {
+    /// fails if the instruction or the account is not in [`crate::ix_accounts::INSTRUCTIONS`]
+    fn with_signer(self, account: &str, is_signer: bool) -> std::result::Result<Self, SignerError>;
+}
+
+impl WithSigner for Instruction {
+    fn with_signer(
+        mut self,
+        account: &str,
+        is_signer: bool,
+    ) -> std::result::Result<Self, SignerError> {
+        let ix_accounts = find_ix_accounts(&self.data).ok_or(SignerError::UnknownInstruction)?;
+        let meta = ix_accounts
+            .position(account)
+            .and_then(|idx| self.accounts.get_mut(idx))
+            .ok_or(SignerError::UnknownAccount(ix_accounts.name))?;
+        meta.is_signer = is_signer;
+        Ok(self)
+    }
+}
+
 pub fn execute_market_ix(
     cypher_group: &Pubkey,
     admin: &Pubkey,
@@ -56,6 +86,30 @@ pub fn execute_market_ix(
     }
 }
 
+pub fn update_market_expiration_time_ix(
+    cypher_group: &Pubkey,
+    admin: &Pubkey,
+    c_asset_mint: &Pubkey,
+    expiration_ts: u64,
+) -> Instruction {
+    let accounts = UpdateMarketExpirationTime {
+        cypher_group: *cypher_group,
+        admin: *admin,
+    };
+    let ix_data = crate::instruction::UpdateMarketExpirationTime {
+        _c_asset_mint: *c_asset_mint,
+        _expiration_ts: expiration_ts,
+    };
+    Instruction {
+        accounts: accounts.to_account_metas(Some(false)),
+        data: get_ix_data(
+            "update_market_expiration_time",
+            AnchorSerialize::try_to_vec(&ix_data).unwrap(),
+        ),
+        program_id: crate::id(),
+    }
+}
+
 pub fn close_market_ix(
     cypher_group: &Pubkey,
     admin: &Pubkey,
@@ -144,66 +198,66 @@ pub fn create_cypher_user_ix(
 pub fn close_cypher_user_ix(
     cypher_group: &Pubkey,
     cypher_user: &Pubkey,
-    owner: &Pubkey,
-) -> Instruction {
+    user_signer: &UserSigner,
+) -> std::result::Result<Instruction, SignerError> {
     let accounts = CloseCypherUser {
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
-        user_signer: *owner,
+        user_signer: user_signer.check(UserAction::CloseAccount)?,
     };
     let ix_data = crate::instruction::CloseCypherUser {};
-    Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+    Ok(Instruction {
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: get_ix_data(
             "close_cypher_user",
             AnchorSerialize::try_to_vec(&ix_data).unwrap(),
         ),
         program_id: crate::id(),
-    }
+    })
 }
 
 pub fn set_delegate_ix(
     cypher_group: &Pubkey,
     cypher_user: &Pubkey,
-    owner: &Pubkey,
+    user_signer: &UserSigner,
     delegate: &Pubkey,
-) -> Instruction {
+) -> std::result::Result<Instruction, SignerError> {
     let accounts = SetDelegate {
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
-        user_signer: *owner,
+        user_signer: user_signer.check(UserAction::SetDelegate)?,
         delegate: *delegate,
     };
     let ix_data = crate::instruction::SetDelegate {};
-    Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+    Ok(Instruction {
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: get_ix_data(
             "set_delegate",
             AnchorSerialize::try_to_vec(&ix_data).unwrap(),
         ),
         program_id: crate::id(),
-    }
+    })
 }
 
 pub fn deposit_collateral_ix(
     cypher_group: &Pubkey,
     cypher_user: &Pubkey,
     cypher_pc_vault: &Pubkey,
-    owner: &Pubkey,
+    user_signer: &UserSigner,
     source_token_account: &Pubkey,
     amount: u64,
 ) -> Instruction {
     let accounts = DepositCollateral {
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
-        user_signer: *owner,
+        user_signer: user_signer.pubkey(),
         cypher_pc_vault: *cypher_pc_vault,
         deposit_from: *source_token_account,
         token_program: token::ID,
     };
     let ix_data = crate::instruction::DepositCollateral { _amount: amount };
     Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: get_ix_data(
             "deposit_collateral",
             AnchorSerialize::try_to_vec(&ix_data).unwrap(),
@@ -217,14 +271,14 @@ pub fn withdraw_collateral_ix(
     cypher_user: &Pubkey,
     cypher_pc_vault: &Pubkey,
     vault_signer: &Pubkey,
-    owner: &Pubkey,
+    user_signer: &UserSigner,
     destination_token_account: &Pubkey,
     amount: u64,
-) -> Instruction {
+) -> std::result::Result<Instruction, SignerError> {
     let accounts = WithdrawCollateral {
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
-        user_signer: *owner,
+        user_signer: user_signer.check(UserAction::Withdraw)?,
         vault_signer: *vault_signer,
         cypher_pc_vault: *cypher_pc_vault,
         withdraw_to: *destination_token_account,
@@ -232,20 +286,20 @@ pub fn withdraw_collateral_ix(
     };
     let ix_data = crate::instruction::WithdrawCollateral { _amount: amount };
 
-    Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+    Ok(Instruction {
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: get_ix_data(
             "withdraw_collateral",
             AnchorSerialize::try_to_vec(&ix_data).unwrap(),
         ),
         program_id: crate::id(),
-    }
+    })
 }
 
 pub fn liquidate_collateral_ix(
     cypher_group: &Pubkey,
     cypher_user: &Pubkey,
-    owner: &Pubkey,
+    user_signer: &UserSigner,
     liqee_cypher_user: &Pubkey,
     asset_mint: &Pubkey,
     liability_mint: &Pubkey,
@@ -253,7 +307,7 @@ pub fn liquidate_collateral_ix(
     let accounts = LiquidateCollateral {
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
-        user_signer: *owner,
+        user_signer: user_signer.pubkey(),
         liqee_cypher_user: *liqee_cypher_user,
     };
     let ix_data = crate::instruction::LiquidateCollateral {
@@ -262,7 +316,7 @@ pub fn liquidate_collateral_ix(
     };
 
     Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: get_ix_data(
             "liquidate_collateral",
             AnchorSerialize::try_to_vec(&ix_data).unwrap(),
@@ -297,7 +351,7 @@ pub fn init_open_orders_ix(
     cypher_group: &Pubkey,
     cypher_user: &Pubkey,
     payer: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     dex_market: &Pubkey,
     open_orders: &Pubkey,
     market_authority: &Pubkey,
@@ -306,7 +360,7 @@ pub fn init_open_orders_ix(
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
         payer: *payer,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         dex_market: *dex_market,
         init_oo_authority: *market_authority,
         open_orders: *open_orders,
@@ -316,7 +370,7 @@ pub fn init_open_orders_ix(
     };
 
     Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::InitOpenOrders.pack(),
         program_id: crate::id(),
     }
@@ -325,21 +379,21 @@ pub fn init_open_orders_ix(
 pub fn close_open_orders_ix(
     cypher_group: &Pubkey,
     cypher_user: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     dex_market: &Pubkey,
     open_orders: &Pubkey,
 ) -> Instruction {
     let accounts = CloseOpenOrders {
         cypher_group: *cypher_group,
         cypher_user: *cypher_user,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         dex_market: *dex_market,
         open_orders: *open_orders,
         dex_program: dex::id(),
     };
 
     Instruction {
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::CloseOpenOrders.pack(),
         program_id: crate::id(),
     }
@@ -350,7 +404,7 @@ pub fn prune_ix(
     cypher_group: &Pubkey,
     vault_signer: &Pubkey,
     cypher_user: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     c_asset_mint: &Pubkey,
     cypher_c_asset_vault: &Pubkey,
     cypher_pc_vault: &Pubkey,
@@ -369,7 +423,7 @@ pub fn prune_ix(
         cypher_group: *cypher_group,
         vault_signer: *vault_signer,
         cypher_user: *cypher_user,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         c_asset_mint: *c_asset_mint,
         cypher_c_asset_vault: *cypher_c_asset_vault,
         cypher_pc_vault: *cypher_pc_vault,
@@ -390,7 +444,7 @@ pub fn prune_ix(
 
     Instruction {
         program_id: crate::id(),
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::Prune(limit).pack(),
     }
 }
@@ -401,7 +455,7 @@ pub fn new_order_v3_ix(
     vault_signer: &Pubkey,
     price_history: &Pubkey,
     cypher_user: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     c_asset_mint: &Pubkey,
     cypher_c_asset_vault: &Pubkey,
     cypher_pc_vault: &Pubkey,
@@ -421,7 +475,7 @@ pub fn new_order_v3_ix(
         vault_signer: *vault_signer,
         price_history: *price_history,
         cypher_user: *cypher_user,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         c_asset_mint: *c_asset_mint,
         cypher_c_asset_vault: *cypher_c_asset_vault,
         cypher_pc_vault: *cypher_pc_vault,
@@ -443,7 +497,7 @@ pub fn new_order_v3_ix(
 
     Instruction {
         program_id: crate::id(),
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::NewOrderV3(data).pack(),
     }
 }
@@ -453,7 +507,7 @@ pub fn cancel_order_v2_ix(
     cypher_group: &Pubkey,
     vault_signer: &Pubkey,
     cypher_user: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     c_asset_mint: &Pubkey,
     cypher_c_asset_vault: &Pubkey,
     cypher_pc_vault: &Pubkey,
@@ -472,7 +526,7 @@ pub fn cancel_order_v2_ix(
         cypher_group: *cypher_group,
         vault_signer: *vault_signer,
         cypher_user: *cypher_user,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         c_asset_mint: *c_asset_mint,
         cypher_c_asset_vault: *cypher_c_asset_vault,
         cypher_pc_vault: *cypher_pc_vault,
@@ -493,7 +547,7 @@ pub fn cancel_order_v2_ix(
 
     Instruction {
         program_id: crate::id(),
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::CancelOrderV2(data).pack(),
     }
 }
@@ -503,7 +557,7 @@ pub fn cancel_order_by_client_id_v2_ix(
     cypher_group: &Pubkey,
     vault_signer: &Pubkey,
     cypher_user: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     c_asset_mint: &Pubkey,
     cypher_c_asset_vault: &Pubkey,
     cypher_pc_vault: &Pubkey,
@@ -522,7 +576,7 @@ pub fn cancel_order_by_client_id_v2_ix(
         cypher_group: *cypher_group,
         vault_signer: *vault_signer,
         cypher_user: *cypher_user,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         c_asset_mint: *c_asset_mint,
         cypher_c_asset_vault: *cypher_c_asset_vault,
         cypher_pc_vault: *cypher_pc_vault,
@@ -543,7 +597,7 @@ pub fn cancel_order_by_client_id_v2_ix(
 
     Instruction {
         program_id: crate::id(),
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::CancelOrderByClientIdV2(client_id).pack(),
     }
 }
@@ -553,7 +607,7 @@ pub fn settle_funds_ix(
     cypher_group: &Pubkey,
     vault_signer: &Pubkey,
     cypher_user: &Pubkey,
-    user_signer: &Pubkey,
+    user_signer: &UserSigner,
     c_asset_mint: &Pubkey,
     cypher_c_asset_vault: &Pubkey,
     cypher_pc_vault: &Pubkey,
@@ -567,7 +621,7 @@ pub fn settle_funds_ix(
         cypher_group: *cypher_group,
         vault_signer: *vault_signer,
         cypher_user: *cypher_user,
-        user_signer: *user_signer,
+        user_signer: user_signer.pubkey(),
         c_asset_mint: *c_asset_mint,
         cypher_c_asset_vault: *cypher_c_asset_vault,
         cypher_pc_vault: *cypher_pc_vault,
@@ -584,7 +638,7 @@ pub fn settle_funds_ix(
 
     Instruction {
         program_id: crate::id(),
-        accounts: accounts.to_account_metas(Some(false)),
+        accounts: user_signer.sign_account_metas(accounts.to_account_metas(Some(false))),
         data: MarketInstruction::SettleFunds.pack(),
     }
 }
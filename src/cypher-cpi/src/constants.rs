@@ -8,3 +8,6 @@ pub const B_OPEN_ORDERS: &[u8] = b"open_orders";
 pub const MARKETS_MAX_CNT: usize = 15;
 pub const TOKENS_MAX_CNT: usize = MARKETS_MAX_CNT + 1;
 pub const QUOTE_TOKEN_IDX: usize = TOKENS_MAX_CNT - 1;
+
+// price history
+pub const PRICE_HISTORY_SIZE: usize = 14400;
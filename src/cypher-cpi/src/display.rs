@@ -0,0 +1,374 @@
+use {
+    crate::{
+        constants::*, orders::get_unit, serum_slab::OrderBookOrder, CypherGroup, CypherMarket,
+        CypherToken, CypherUser, UserPosition,
+    },
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+    std::fmt::{Display, Formatter, Result},
+};
+
+/// converts a native amount into a decimal-adjusted amount
+///
+/// the native amount is returned as is if the decimals are too large for a token unit to fit in a `u64`, so that a
+/// bad token config does not crash the logger
+pub fn ui_amount(amount: Number, decimals: u8) -> Number {
+    match get_unit(decimals) {
+        Ok(unit) => amount / unit,
+        Err(_) => amount,
+    }
+}
+
+/// a symbolic name for a token or market index
+pub struct TokenIdx(pub usize);
+
+impl Display for TokenIdx {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        if self.0 == QUOTE_TOKEN_IDX {
+            write!(f, "quote")
+        } else {
+            write!(f, "#{}", self.0)
+        }
+    }
+}
+
+impl Display for CypherToken {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let mint = self.mint;
+        let decimals = self.decimals();
+        write!(
+            f,
+            "token(mint={}, decimals={}, deposits={}, borrows={})",
+            mint,
+            decimals,
+            ui_amount(self.total_deposits(), decimals),
+            ui_amount(self.total_borrows(), decimals),
+        )
+    }
+}
+
+impl Display for CypherMarket {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let dex_market = self.dex_market;
+        let market_price = self.market_price;
+        let expires_at = self.expires_at;
+        let is_executed = self.is_executed;
+        write!(
+            f,
+            "market(dex_market={}, type={}, market_price={}, oracle_price={}, expires_at={}, executed={})",
+            dex_market,
+            self.market_type_name(),
+            market_price,
+            self.oracle_price(),
+            expires_at,
+            is_executed,
+        )
+    }
+}
+
+impl Display for CypherGroup {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let self_address = self.self_address;
+        let insurance_fund = self.insurance_fund;
+        let protocol_fee = self.protocol_fee;
+        write!(
+            f,
+            "group(address={}, insurance_fund={}, protocol_fee={}, init={}, maint={}, partial={}, tokens=[",
+            self_address,
+            insurance_fund,
+            protocol_fee,
+            self.margin_init_ratio(),
+            self.margin_maint_ratio(),
+            self.margin_partial_ratio(),
+        )?;
+        let mut sep = "";
+        for idx in 0..TOKENS_MAX_CNT {
+            if let Some(token) = self.get_cypher_token(idx) {
+                write!(f, "{}{}: {}", sep, TokenIdx(idx), token)?;
+                sep = ", ";
+            }
+        }
+        write!(f, "], markets=[")?;
+        let mut sep = "";
+        for idx in 0..MARKETS_MAX_CNT {
+            if let Some(market) = self.get_cypher_market(idx) {
+                write!(f, "{}{}: {}", sep, TokenIdx(idx), market)?;
+                sep = ", ";
+            }
+        }
+        write!(f, "])")
+    }
+}
+
+fn fmt_position(
+    f: &mut Formatter,
+    token_idx: usize,
+    position: &UserPosition,
+    deposits: Number,
+    borrows: Number,
+    decimals: Option<u8>,
+) -> Result {
+    let (deposits, borrows) = match decimals {
+        Some(decimals) => (ui_amount(deposits, decimals), ui_amount(borrows, decimals)),
+        None => (deposits, borrows),
+    };
+    write!(
+        f,
+        "position(market={}, deposits={}, borrows={}",
+        TokenIdx(token_idx),
+        deposits,
+        borrows
+    )?;
+    let oo_info = &position.oo_info;
+    if oo_info.is_account_open {
+        let coin_total = oo_info.coin_total;
+        let pc_total = oo_info.pc_total;
+        write!(
+            f,
+            ", oo_coin_total={}, oo_pc_total={}",
+            coin_total, pc_total
+        )?;
+    }
+    write!(f, ")")
+}
+
+/// displays the position's base amounts in native units, see [`CypherUser::display`] for decimal-adjusted amounts
+impl Display for UserPosition {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        fmt_position(
+            f,
+            self.market_idx as usize,
+            self,
+            self.base_deposits(),
+            self.base_borrows(),
+            None,
+        )
+    }
+}
+
+fn fmt_user(f: &mut Formatter, user: &CypherUser, group: Option<&CypherGroup>) -> Result {
+    let owner = user.user_signer;
+    let delegate = user.delegate;
+    write!(f, "user(owner={}", owner)?;
+    if delegate != Pubkey::default() {
+        write!(f, ", delegate={}", delegate)?;
+    }
+    write!(f, ", positions=[")?;
+    let mut sep = "";
+    let quote_position = user
+        .get_position(QUOTE_TOKEN_IDX)
+        .map(|p| (QUOTE_TOKEN_IDX, p));
+    let positions = user.iter_positions().map(|p| (p.market_idx as usize, p));
+    for (token_idx, position) in quote_position.into_iter().chain(positions) {
+        let token = group.and_then(|g| g.get_cypher_token(token_idx));
+        let (deposits, borrows) = match token {
+            Some(token) if token_idx == QUOTE_TOKEN_IDX => (
+                position.total_deposits(token),
+                position.total_borrows(token),
+            ),
+            _ => (position.base_deposits(), position.base_borrows()),
+        };
+        if deposits == Number::ZERO && borrows == Number::ZERO && !position.oo_info.is_account_open
+        {
+            continue;
+        }
+        write!(f, "{}", sep)?;
+        fmt_position(
+            f,
+            token_idx,
+            position,
+            deposits,
+            borrows,
+            token.map(|t| t.decimals()),
+        )?;
+        sep = ", ";
+    }
+    write!(f, "]")?;
+    if let Some(group) = group {
+        match user.get_margin_c_ratio_components(group) {
+            Ok((c_ratio, assets_value, liabs_value)) => {
+                write!(f, ", assets={}, liabilities={}", assets_value, liabs_value)?;
+                if liabs_value != Number::ZERO {
+                    write!(f, ", c_ratio={}", c_ratio)?;
+                }
+            }
+            Err(err) => write!(f, ", margin_error={:?}", err)?,
+        }
+    }
+    write!(f, ")")
+}
+
+/// displays the user's base amounts in native units, see [`CypherUser::display`] for decimal-adjusted amounts
+impl Display for CypherUser {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        fmt_user(f, self, None)
+    }
+}
+
+/// displays a cypher user with decimal-adjusted amounts and its margin values
+pub struct UserDisplay<'a> {
+    user: &'a CypherUser,
+    group: &'a CypherGroup,
+}
+
+impl Display for UserDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        fmt_user(f, self.user, Some(self.group))
+    }
+}
+
+impl CypherUser {
+    /// displays the user with amounts adjusted for the decimals of the group's tokens
+    pub fn display<'a>(&'a self, group: &'a CypherGroup) -> UserDisplay<'a> {
+        UserDisplay { user: self, group }
+    }
+}
+
+impl Display for OrderBookOrder {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(
+            f,
+            "order(id={}, client_id={}, price={}, quantity={})",
+            self.order_id, self.client_order_id, self.price, self.quantity
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*};
+
+    struct Fixture {
+        group: Box<CypherGroup>,
+        user: Box<CypherUser>,
+        mint: Pubkey,
+        dex_market: Pubkey,
+        owner: Pubkey,
+    }
+
+    fn fixture() -> Fixture {
+        let mint = Pubkey::new_unique();
+        let dex_market = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let group = CypherGroupBuilder::new()
+            .fund(1_000, 20)
+            .market(0, mint, 9, 40)
+            .dex_market(0, dex_market)
+            .token_balances(
+                0,
+                Number::from(2_500_000_000_u64),
+                Number::from(500_000_000_u64),
+            )
+            .build();
+        let user = CypherUserBuilder::new(group.self_address, owner)
+            .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+            .deposit(0, 2_000_000_000)
+            .open_orders(0, 0, 5_000_000)
+            .borrow(1, 0)
+            .build();
+        Fixture {
+            group,
+            user,
+            mint,
+            dex_market,
+            owner,
+        }
+    }
+
+    #[test]
+    fn ui_amount_falls_back_to_native_amount() {
+        assert_eq!(ui_amount(Number::from(2_500_000_u64), 6).to_string(), "2.5");
+        assert_eq!(ui_amount(Number::from(123_u64), 20).to_string(), "123.0");
+    }
+
+    #[test]
+    fn displays_tokens_and_markets() {
+        let f = fixture();
+        assert_eq!(TokenIdx(3).to_string(), "#3");
+        assert_eq!(TokenIdx(QUOTE_TOKEN_IDX).to_string(), "quote");
+        assert_eq!(
+            f.group.get_cypher_token(0).unwrap().to_string(),
+            format!(
+                "token(mint={}, decimals=9, deposits=2.5, borrows=0.5)",
+                f.mint
+            )
+        );
+        assert_eq!(
+            f.group.get_cypher_market(0).unwrap().to_string(),
+            format!(
+                "market(dex_market={}, type=PairFuture, market_price=40, oracle_price=40, expires_at=0, executed=false)",
+                f.dex_market
+            )
+        );
+    }
+
+    #[test]
+    fn displays_group_with_listed_slots_only() {
+        let f = fixture();
+        assert_eq!(
+            f.group.to_string(),
+            format!(
+                "group(address={}, insurance_fund=1000, protocol_fee=20, init=1.5, maint=1.1, partial=1.3, \
+                 tokens=[#0: token(mint={}, decimals=9, deposits=2.5, borrows=0.5), \
+                 quote: token(mint={}, decimals=6, deposits=0.0, borrows=0.0)], \
+                 markets=[#0: market(dex_market={}, type=PairFuture, market_price=40, oracle_price=40, expires_at=0, executed=false)])",
+                f.group.self_address,
+                f.mint,
+                crate::quote_mint::ID,
+                f.dex_market
+            )
+        );
+    }
+
+    #[test]
+    fn displays_users_without_empty_positions_or_delegate() {
+        let f = fixture();
+        assert_eq!(
+            f.user.positions[0].to_string(),
+            "position(market=#0, deposits=2000000000.0, borrows=0.0, oo_coin_total=0, oo_pc_total=5000000)"
+        );
+        assert_eq!(
+            f.user.to_string(),
+            format!(
+                "user(owner={}, positions=[position(market=quote, deposits=100000000.0, borrows=0.0), \
+                 position(market=#0, deposits=2000000000.0, borrows=0.0, oo_coin_total=0, oo_pc_total=5000000)])",
+                f.owner
+            )
+        );
+        assert_eq!(
+            f.user.display(&f.group).to_string(),
+            format!(
+                "user(owner={}, positions=[position(market=quote, deposits=100.0, borrows=0.0), \
+                 position(market=#0, deposits=2.0, borrows=0.0, oo_coin_total=0, oo_pc_total=5000000)], \
+                 assets=80105000000.0, liabilities=0.0)",
+                f.owner
+            )
+        );
+
+        let delegate = Pubkey::new_unique();
+        let user = CypherUserBuilder::new(f.group.self_address, f.owner)
+            .delegate(delegate)
+            .build();
+        assert_eq!(
+            user.to_string(),
+            format!(
+                "user(owner={}, delegate={}, positions=[])",
+                f.owner, delegate
+            )
+        );
+    }
+
+    #[test]
+    fn displays_orders() {
+        let order = OrderBookOrder {
+            price: 4_000,
+            quantity: 25,
+            order_id: 7,
+            client_order_id: 3,
+        };
+        assert_eq!(
+            order.to_string(),
+            "order(id=7, client_id=3, price=4000, quantity=25)"
+        );
+    }
+}
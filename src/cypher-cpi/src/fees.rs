@@ -0,0 +1,139 @@
+//! fee math for fills on cypher markets.
+//!
+//! - the cypher fee is computed from the group's `maker_fee_bps` and `taker_fee_bps` on a fill's quote amount, and
+//!   split with `fee_percentage_for_protocol` and `fee_percentage_for_insurance_fund`. the rates are unsigned, so
+//!   there is no cypher maker rebate. the idl only declares these fields, it does not say how the cypher program
+//!   applies them, so the fees computed here are estimates.
+//! - the serum fee is set by the `FeeTier` of the open orders account and computed with `serum_dex::fees`.
+use {
+    crate::{serum_slab::FeeTier, CypherGroup, OrderFillLog},
+    jet_proto_math::Number,
+    std::convert::TryFrom,
+};
+
+/// the cypher fee of a single fill, in native quote units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FillFee {
+    pub fee: u64,
+    pub protocol_share: u64,
+    /// the insurance fund's share, rounding dust is attributed to neither share
+    pub insurance_fund_share: u64,
+}
+
+/// the cypher fee parameters of a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+    pub fee_percentage_for_protocol: u8,
+    pub fee_percentage_for_insurance_fund: u8,
+}
+
+impl FeeSchedule {
+    pub fn new(group: &CypherGroup) -> Self {
+        let config = group.config;
+        Self {
+            maker_fee_bps: config.maker_fee_bps,
+            taker_fee_bps: config.taker_fee_bps,
+            fee_percentage_for_protocol: config.fee_percentage_for_protocol,
+            fee_percentage_for_insurance_fund: config.fee_percentage_for_insurance_fund,
+        }
+    }
+
+    /// gets the maker fee rate
+    pub fn maker_fee(&self) -> Number {
+        Number::from_bps(self.maker_fee_bps)
+    }
+
+    /// gets the taker fee rate
+    pub fn taker_fee(&self) -> Number {
+        Number::from_bps(self.taker_fee_bps)
+    }
+
+    /// computes the cypher fee for a fill of `native_pc_qty`, rounded up
+    pub fn get_fill_fee(&self, native_pc_qty: u64, is_taker: bool) -> FillFee {
+        let rate = if is_taker {
+            self.taker_fee()
+        } else {
+            self.maker_fee()
+        };
+        let fee = (Number::from(native_pc_qty) * rate).as_u64_ceil(0);
+        FillFee {
+            fee,
+            protocol_share: (fee as u128 * self.fee_percentage_for_protocol as u128 / 100) as u64,
+            insurance_fund_share: (fee as u128 * self.fee_percentage_for_insurance_fund as u128
+                / 100) as u64,
+        }
+    }
+
+    /// computes the cypher fee for a logged fill
+    pub fn get_order_fill_fee(&self, log: &OrderFillLog) -> FillFee {
+        self.get_fill_fee(log.pc_qty, log.is_taker)
+    }
+}
+
+impl CypherGroup {
+    /// gets the group's fee schedule
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        FeeSchedule::new(self)
+    }
+}
+
+impl FeeTier {
+    /// gets the serum fee tier, `None` if serum has no tier with the same value
+    fn to_serum(self) -> Option<serum_dex::fees::FeeTier> {
+        serum_dex::fees::FeeTier::try_from(u8::from(self)).ok()
+    }
+
+    /// gets the serum fee tier for the given srm and msrm balances of the open orders account's owner,
+    /// `None` if this crate has no tier with the same value
+    pub fn from_srm_and_msrm_balances(srm_held: u64, msrm_held: u64) -> Option<Self> {
+        let tier = serum_dex::fees::FeeTier::from_srm_and_msrm_balances(srm_held, msrm_held);
+        FeeTier::try_from(u8::from(tier)).ok()
+    }
+
+    /// gets the serum taker fee for a fill of `native_pc_qty`
+    pub fn taker_fee(self, native_pc_qty: u64) -> Option<u64> {
+        Some(self.to_serum()?.taker_fee(native_pc_qty))
+    }
+
+    /// gets the serum maker rebate for a fill of `native_pc_qty`
+    pub fn maker_rebate(self, native_pc_qty: u64) -> Option<u64> {
+        Some(self.to_serum()?.maker_rebate(native_pc_qty))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_fee_is_rounded_up_and_split() {
+        let schedule = FeeSchedule {
+            maker_fee_bps: 0,
+            taker_fee_bps: 10,
+            fee_percentage_for_protocol: 60,
+            fee_percentage_for_insurance_fund: 40,
+        };
+        assert_eq!(
+            schedule.get_fill_fee(1_000_001, true),
+            FillFee {
+                fee: 1_001,
+                protocol_share: 600,
+                insurance_fund_share: 400,
+            }
+        );
+        assert_eq!(schedule.get_fill_fee(1_000_001, false), FillFee::default());
+    }
+
+    #[test]
+    fn fee_tiers_convert_without_panicking() {
+        assert_eq!(
+            FeeTier::from_srm_and_msrm_balances(0, 0).map(u8::from),
+            Some(u8::from(FeeTier::Base))
+        );
+        assert!(FeeTier::Base.taker_fee(1_000_000).is_some());
+        assert!(FeeTier::Base.maker_rebate(1_000_000).is_some());
+        assert!(FeeTier::try_from(u8::MAX).is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! taker order simulation against a decoded bids or asks `Slab`, used to size immediate-or-cancel orders.
+use {
+    crate::{
+        orders::{IntoNumber, OrderContext, OrderError},
+        serum_slab::Slab,
+        utils::checked_as_u64,
+    },
+    jet_proto_math::Number,
+    serum_dex::matching::Side,
+    std::convert::TryFrom,
+};
+
+/// the outcome of a simulated taker order, prices are in quote per coin and sizes in coin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FillSimulation {
+    pub filled_lots: u64,
+    pub remaining_lots: u64,
+    /// the quote amount exchanged in native units, excluding fees
+    pub native_pc_qty: u64,
+    /// the taker fee in native quote units
+    pub native_fee: u64,
+    /// the average fill price, `None` if nothing was filled
+    pub average_price: Option<Number>,
+    /// the price of the last order matched, `None` if nothing was filled
+    pub worst_price: Option<Number>,
+    pub filled_size: Number,
+    pub remaining_size: Number,
+    /// the taker fee in quote
+    pub fee: Number,
+}
+
+impl FillSimulation {
+    fn new(
+        ctx: &OrderContext,
+        filled_lots: u64,
+        remaining_lots: u64,
+        pc_lots: u128,
+        worst_price_lots: Option<u64>,
+    ) -> Result<Self, OrderError> {
+        let native_pc_qty = pc_lots
+            .checked_mul(ctx.pc_lot_size as u128)
+            .and_then(|qty| u64::try_from(qty).ok())
+            .ok_or(OrderError::AmountOverflow)?;
+        let native_fee = (Number::from(native_pc_qty) * ctx.taker_fee()).as_u64_ceil(0);
+        let pc_unit = ctx.pc_unit()?;
+        let filled_size = ctx.lots_to_size(filled_lots)?;
+        let average_price = if filled_lots == 0 {
+            None
+        } else {
+            Some(Number::from(native_pc_qty) / pc_unit / filled_size)
+        };
+        Ok(Self {
+            filled_lots,
+            remaining_lots,
+            native_pc_qty,
+            native_fee,
+            average_price,
+            worst_price: worst_price_lots.map(|p| ctx.lots_to_price(p)).transpose()?,
+            filled_size,
+            remaining_size: ctx.lots_to_size(remaining_lots)?,
+            fee: Number::from(native_fee) / pc_unit,
+        })
+    }
+
+    pub fn is_fully_filled(&self) -> bool {
+        self.remaining_lots == 0
+    }
+}
+
+/// simulates a taker order of `size_lots` against the opposite side of the book
+///
+/// `book` must hold the asks for a bid and the bids for an ask
+pub fn simulate_taker_order_lots(
+    ctx: &OrderContext,
+    book: &Slab,
+    side: Side,
+    size_lots: u64,
+) -> Result<FillSimulation, OrderError> {
+    let mut remaining_lots = size_lots;
+    let mut pc_lots = 0_u128;
+    let mut worst_price_lots = None;
+    for order in book.get_orders(side == Side::Bid) {
+        if remaining_lots == 0 {
+            break;
+        }
+        let price_lots = u64::from(order.price());
+        let fill_lots = remaining_lots.min(order.quantity());
+        pc_lots += fill_lots as u128 * price_lots as u128;
+        remaining_lots -= fill_lots;
+        worst_price_lots = Some(price_lots);
+    }
+    FillSimulation::new(
+        ctx,
+        size_lots - remaining_lots,
+        remaining_lots,
+        pc_lots,
+        worst_price_lots,
+    )
+}
+
+/// simulates a taker order of the given size in coin, see [`simulate_taker_order_lots`]
+///
+/// the size is rounded down to a whole number of lots
+pub fn simulate_taker_order(
+    ctx: &OrderContext,
+    book: &Slab,
+    side: Side,
+    size: impl IntoNumber,
+) -> Result<FillSimulation, OrderError> {
+    let size = size.into_number().ok_or(OrderError::InvalidSize)?;
+    let size_lots = checked_as_u64(ctx.size_to_lots(size)?)
+        .filter(|size_lots| *size_lots > 0)
+        .ok_or(OrderError::InvalidSize)?;
+    simulate_taker_order_lots(ctx, book, side, size_lots)
+}
+
+/// simulates the largest taker order whose average fill price stays within `slippage` of the best price in the book
+///
+/// the slippage is a fraction, `0.01` allows the average price to be 1% worse than the best price,
+/// `None` is returned if the book is empty
+pub fn max_size_within_slippage(
+    ctx: &OrderContext,
+    book: &Slab,
+    side: Side,
+    slippage: impl IntoNumber,
+) -> Result<Option<FillSimulation>, OrderError> {
+    let slippage = slippage.into_number().ok_or(OrderError::InvalidSlippage)?;
+    let orders = book.get_orders(side == Side::Bid);
+    let best_price_lots = match orders.first() {
+        Some(order) => u64::from(order.price()),
+        None => return Ok(None),
+    };
+    let limit = match side {
+        Side::Bid => Number::from(best_price_lots) * (Number::ONE + slippage),
+        Side::Ask if slippage <= Number::ONE => {
+            Number::from(best_price_lots) * (Number::ONE - slippage)
+        }
+        Side::Ask => return Err(OrderError::InvalidSlippage),
+    };
+
+    // the average price of `size_lots` for `pc_lots` is within the limit as long as `pc_lots <= limit * size_lots`
+    // for bids, or `pc_lots >= limit * size_lots` for asks, the last order is filled partially up to the limit
+    let mut size_lots = 0_u64;
+    let mut pc_lots = Number::ZERO;
+    for order in orders {
+        let price = Number::from(u64::from(order.price()));
+        let quantity = order.quantity();
+        let new_pc_lots = pc_lots + price * quantity;
+        let new_limit = limit * (size_lots + quantity);
+        let within = match side {
+            Side::Bid => new_pc_lots <= new_limit,
+            Side::Ask => new_pc_lots >= new_limit,
+        };
+        if within {
+            size_lots += quantity;
+            pc_lots = new_pc_lots;
+            continue;
+        }
+        // the order is not within the limit because its price is worse than the limit, and the orders before it are,
+        // so both differences are positive, they are still checked since `Number` is unsigned
+        let (pc_margin, price_margin) = match side {
+            Side::Bid => (
+                positive_diff(limit * size_lots, pc_lots),
+                positive_diff(price, limit),
+            ),
+            Side::Ask => (
+                positive_diff(pc_lots, limit * size_lots),
+                positive_diff(limit, price),
+            ),
+        };
+        if price_margin > Number::ZERO {
+            // clamped before converting, the quotient of a tiny price margin does not fit in a `u64`
+            let fill_lots = pc_margin / price_margin;
+            let quantity = Number::from(quantity);
+            size_lots += if fill_lots < quantity {
+                fill_lots
+            } else {
+                quantity
+            }
+            .as_u64(0);
+        }
+        break;
+    }
+    simulate_taker_order_lots(ctx, book, side, size_lots).map(Some)
+}
+
+/// computes `a - b`, zero if `b` is larger
+fn positive_diff(a: Number, b: Number) -> Number {
+    if a > b {
+        a - b
+    } else {
+        Number::ZERO
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::SlabBuilder, anchor_lang::prelude::Pubkey};
+
+    /// one coin lot is 0.001 coin and a price of 100 pc lots per coin lot is 1 quote per coin
+    fn ctx() -> OrderContext {
+        OrderContext {
+            coin_lot_size: 1_000,
+            pc_lot_size: 10,
+            coin_decimals: 6,
+            pc_decimals: 6,
+            taker_fee_bps: 10,
+        }
+    }
+
+    fn asks() -> Vec<u8> {
+        let owner = Pubkey::new_unique();
+        SlabBuilder::asks()
+            .order(1, 4_000, 100, &owner)
+            .order(2, 4_000, 50, &owner)
+            .order(3, 4_100, 200, &owner)
+            .build()
+    }
+
+    fn bids() -> Vec<u8> {
+        let owner = Pubkey::new_unique();
+        SlabBuilder::bids()
+            .order(1, 4_000, 100, &owner)
+            .order(2, 3_900, 100, &owner)
+            .build()
+    }
+
+    #[test]
+    fn full_fill() {
+        let ctx = ctx();
+        let asks = asks();
+        let sim = simulate_taker_order_lots(&ctx, Slab::from_bytes(&asks), Side::Bid, 120).unwrap();
+        assert!(sim.is_fully_filled());
+        assert_eq!(sim.filled_lots, 120);
+        assert_eq!(sim.native_pc_qty, 120 * 4_000 * 10);
+        assert_eq!(sim.native_fee, 4_800);
+        assert_eq!(sim.average_price, Some(Number::from(40_u64)));
+        assert_eq!(sim.worst_price, Some(Number::from(40_u64)));
+        assert_eq!(sim.filled_size, ctx.lots_to_size(120).unwrap());
+    }
+
+    #[test]
+    fn partial_level_and_unfilled_remainder() {
+        let ctx = ctx();
+        let asks = asks();
+        let sim = simulate_taker_order_lots(&ctx, Slab::from_bytes(&asks), Side::Bid, 200).unwrap();
+        assert!(sim.is_fully_filled());
+        assert_eq!(sim.native_pc_qty, (150 * 4_000 + 50 * 4_100) * 10);
+        assert_eq!(sim.worst_price, Some(Number::from(41_u64)));
+
+        let sim = simulate_taker_order_lots(&ctx, Slab::from_bytes(&asks), Side::Bid, 400).unwrap();
+        assert_eq!((sim.filled_lots, sim.remaining_lots), (350, 50));
+        assert_eq!(sim.remaining_size, ctx.lots_to_size(50).unwrap());
+    }
+
+    #[test]
+    fn empty_book() {
+        let ctx = ctx();
+        let book = SlabBuilder::asks().build();
+        let sim = simulate_taker_order_lots(&ctx, Slab::from_bytes(&book), Side::Bid, 10).unwrap();
+        assert_eq!((sim.filled_lots, sim.remaining_lots), (0, 10));
+        assert_eq!((sim.average_price, sim.worst_price), (None, None));
+        assert_eq!(sim.native_fee, 0);
+        assert_eq!(
+            max_size_within_slippage(&ctx, Slab::from_bytes(&book), Side::Bid, 0.01),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn max_size_stays_within_slippage() {
+        let ctx = ctx();
+        let asks = asks();
+        // the limit is 40.4, the first level is filled and the last one up to the limit
+        let sim = max_size_within_slippage(&ctx, Slab::from_bytes(&asks), Side::Bid, 0.01)
+            .unwrap()
+            .unwrap();
+        assert_eq!(sim.filled_lots, 250);
+        assert_eq!(sim.average_price, Some(ctx.lots_to_price(4_040).unwrap()));
+
+        let bids = bids();
+        // the limit is 39.6, the second level is filled partially
+        let sim = max_size_within_slippage(&ctx, Slab::from_bytes(&bids), Side::Ask, 0.01)
+            .unwrap()
+            .unwrap();
+        assert_eq!(sim.filled_lots, 166);
+        assert!(sim.average_price.unwrap() >= ctx.lots_to_price(3_960).unwrap());
+        // both levels are within a 2% limit of 39.2
+        let sim = max_size_within_slippage(&ctx, Slab::from_bytes(&bids), Side::Ask, 0.02)
+            .unwrap()
+            .unwrap();
+        assert_eq!(sim.filled_lots, 200);
+
+        assert_eq!(
+            max_size_within_slippage(&ctx, Slab::from_bytes(&bids), Side::Ask, 1.5),
+            Err(OrderError::InvalidSlippage)
+        );
+    }
+
+    #[test]
+    fn size_overflow_is_an_error() {
+        let asks = asks();
+        assert_eq!(
+            simulate_taker_order(
+                &ctx(),
+                Slab::from_bytes(&asks),
+                Side::Bid,
+                Number::from(u64::MAX)
+            ),
+            Err(OrderError::InvalidSize)
+        );
+        assert_eq!(
+            simulate_taker_order(&ctx(), Slab::from_bytes(&asks), Side::Bid, 0.0001),
+            Err(OrderError::InvalidSize)
+        );
+        let sim = simulate_taker_order(&ctx(), Slab::from_bytes(&asks), Side::Bid, 0.12).unwrap();
+        assert_eq!(sim.filled_lots, 120);
+    }
+
+    #[test]
+    fn quote_amount_overflow_is_an_error() {
+        let book = SlabBuilder::asks()
+            .order(1, u64::MAX / 2, 4, &Pubkey::new_unique())
+            .build();
+        assert_eq!(
+            simulate_taker_order_lots(&ctx(), Slab::from_bytes(&book), Side::Bid, 4),
+            Err(OrderError::AmountOverflow)
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! compatibility checks of the generated types against the bundled idl.
+//!
+//! the expected size and field offsets of every account and type are computed from `idl.json` and compared with
+//! the rust types, the account sizes are also compared with the pinned data lengths of the on-chain accounts, and the
+//! accounts of every instruction with the table of [`crate::ix_accounts`], every mismatch is reported at once.
+use {
+    crate::{
+        ix_accounts::INSTRUCTIONS, layout::VersionedAccount, test_utils::*, CypherGroup,
+        CypherGroupConfig, CypherMarket, CypherMarketConfig, CypherToken, CypherTokenConfig,
+        CypherUser, MarketType, OpenOrdersInfo, OraclePrice, PriceHistory, PriceHistoryConfig,
+        PriceWithTs, UserPosition,
+    },
+    anchor_lang::prelude::Pubkey,
+    serde_json::Value,
+    std::{collections::BTreeSet, mem::size_of},
+};
+
+const IDL: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/idl.json"));
+
+/// the idl definitions without a rust layout to compare: `PythProducts` is a borsh account holding vectors, so it
+/// has no fixed size, and `InitCypherGroupArgs` (24 bytes) and `InitMarketArgs` (74 bytes) are instruction arguments
+/// rather than account data
+const SKIPPED: &[&str] = &["PythProducts", "InitCypherGroupArgs", "InitMarketArgs"];
+
+/// the layout of a rust type, with its field names and offsets in declaration order
+struct RustLayout {
+    name: &'static str,
+    size: usize,
+    fields: Vec<(&'static str, usize)>,
+}
+
+macro_rules! rust_layout {
+    ($ty:ident { $($field:ident),* $(,)? }) => {{
+        let value = std::mem::MaybeUninit::<$ty>::uninit();
+        let base = value.as_ptr();
+        RustLayout {
+            name: stringify!($ty),
+            size: size_of::<$ty>(),
+            fields: vec![$((
+                stringify!($field),
+                unsafe { std::ptr::addr_of!((*base).$field) as usize - base as usize },
+            )),*],
+        }
+    }};
+    ($ty:ident) => {
+        RustLayout {
+            name: stringify!($ty),
+            size: size_of::<$ty>(),
+            fields: vec![],
+        }
+    };
+}
+
+fn rust_layouts() -> Vec<RustLayout> {
+    vec![
+        rust_layout!(CypherGroup {
+            self_address,
+            admin,
+            vault_signer,
+            insurance_fund,
+            markets,
+            tokens,
+            config,
+            vault_signer_bump_seed,
+            padding1,
+            protocol_fee,
+            padding2,
+        }),
+        rust_layout!(CypherUser {
+            cypher_group,
+            user_signer,
+            positions,
+            authority_bump_seed,
+            padding1,
+            delegate,
+            padding2,
+        }),
+        rust_layout!(PriceHistory {
+            cypher_group,
+            c_asset_mint,
+            data,
+            config,
+            longer_head,
+            shorter_head,
+            tail,
+            padding1,
+            padding2,
+        }),
+        rust_layout!(CypherGroupConfig {
+            margin_init_ratio,
+            margin_maint_ratio,
+            margin_partial_ratio,
+            liquidator_bonus_bps,
+            liquidation_insurance_fee_bps,
+            maker_fee_bps,
+            taker_fee_bps,
+            fee_percentage_for_protocol,
+            fee_percentage_for_insurance_fund,
+            padding,
+        }),
+        rust_layout!(CypherMarket {
+            dex_market,
+            price_history,
+            pyth_products,
+            oracle_price,
+            market_price,
+            positions_count,
+            listed_at,
+            expires_at,
+            market_type,
+            is_executed,
+            dex_market_authority_bump_seed,
+            padding1,
+            padding2,
+            padding3,
+        }),
+        rust_layout!(OraclePrice { price, cached_slot }),
+        rust_layout!(CypherMarketConfig {
+            mint_init_ratio,
+            mint_maint_ratio,
+            mint_partial_ratio,
+            padding,
+        }),
+        rust_layout!(CypherToken {
+            mint,
+            vault,
+            base_deposits,
+            base_borrows,
+            deposit_index,
+            borrow_index,
+            accum_deposit_interest_payment,
+            accum_borrow_interest_payment,
+            index_updated_at,
+            accum_borrows,
+            accum_repays,
+            config,
+            padding,
+        }),
+        rust_layout!(CypherTokenConfig {
+            optimal_util,
+            optimal_apr,
+            max_apr,
+            decimals,
+            padding,
+        }),
+        rust_layout!(PriceHistoryConfig {
+            longer_time_horizon,
+            shorter_time_horizon,
+            price_collection_tick,
+        }),
+        rust_layout!(PriceWithTs { price, timestamp }),
+        rust_layout!(UserPosition {
+            base_deposits,
+            base_borrows,
+            oo_info,
+            market_idx,
+            padding,
+            padding2,
+        }),
+        rust_layout!(OpenOrdersInfo {
+            coin_total,
+            coin_free,
+            pc_total,
+            pc_free,
+            referrer_rebates_accrued,
+            is_account_open,
+            padding,
+        }),
+        rust_layout!(MarketType),
+    ]
+}
+
+/// the idl definitions of the accounts and types, by name, and the idl instructions
+struct Idl {
+    defs: Vec<(String, Value)>,
+    instructions: Vec<Value>,
+}
+
+impl Idl {
+    fn load() -> Self {
+        let idl: Value = serde_json::from_str(IDL).expect("idl.json is not valid json");
+        let defs = ["accounts", "types"]
+            .iter()
+            .flat_map(|key| idl[*key].as_array().cloned().unwrap_or_default())
+            .map(|def| {
+                (
+                    def["name"].as_str().unwrap().to_string(),
+                    def["type"].clone(),
+                )
+            })
+            .collect();
+        let instructions = idl["instructions"].as_array().cloned().unwrap_or_default();
+        Self { defs, instructions }
+    }
+
+    fn get_def(&self, name: &str) -> &Value {
+        &self
+            .defs
+            .iter()
+            .find(|(n, _)| n == name)
+            .unwrap_or_else(|| panic!("{} is not defined in the idl", name))
+            .1
+    }
+
+    /// gets the borsh size of the given idl type, which is also its zero-copy size, `None` if it is variable
+    fn get_size(&self, ty: &Value) -> Option<usize> {
+        if let Some(primitive) = ty.as_str() {
+            return Some(match primitive {
+                "bool" | "u8" | "i8" => 1,
+                "u16" | "i16" => 2,
+                "u32" | "i32" | "f32" => 4,
+                "u64" | "i64" | "f64" => 8,
+                "u128" | "i128" => 16,
+                "publicKey" => 32,
+                _ => return None,
+            });
+        }
+        if let Some(array) = ty.get("array") {
+            return Some(self.get_size(&array[0])? * array[1].as_u64()? as usize);
+        }
+        if let Some(defined) = ty.get("defined") {
+            return self.get_def_size(defined.as_str()?);
+        }
+        None
+    }
+
+    fn get_def_size(&self, name: &str) -> Option<usize> {
+        let def = self.get_def(name);
+        match def["kind"].as_str()? {
+            "struct" => Some(self.get_fields(name)?.iter().map(|(_, _, size)| size).sum()),
+            "enum" => {
+                let variants = def["variants"].as_array()?;
+                if variants.iter().any(|v| v.get("fields").is_some()) {
+                    return None;
+                }
+                Some(1)
+            }
+            _ => None,
+        }
+    }
+
+    /// gets the snake case name, offset and size of every field of the given struct, `None` if any is variable
+    fn get_fields(&self, name: &str) -> Option<Vec<(String, usize, usize)>> {
+        let mut offset = 0;
+        let mut fields = Vec::new();
+        for field in self.get_def(name)["fields"].as_array()? {
+            let size = self.get_size(&field["type"])?;
+            fields.push((to_snake_case(field["name"].as_str()?), offset, size));
+            offset += size;
+        }
+        Some(fields)
+    }
+}
+
+/// flattens the accounts of an idl instruction in account meta order, prefixing nested accounts with their group
+fn flatten_ix_accounts(accounts: &Value, prefix: &str, flat: &mut Vec<(String, bool, bool)>) {
+    for account in accounts.as_array().unwrap() {
+        let name = format!(
+            "{}{}",
+            prefix,
+            to_snake_case(account["name"].as_str().unwrap())
+        );
+        if let Some(nested) = account.get("accounts") {
+            flatten_ix_accounts(nested, &format!("{}.", name), flat);
+        } else {
+            flat.push((
+                name,
+                account["isMut"].as_bool().unwrap(),
+                account["isSigner"].as_bool().unwrap(),
+            ));
+        }
+    }
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+/// compares the rust layout with the idl, returning every mismatch
+fn check_layout(idl: &Idl, layout: &RustLayout) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    match idl.get_def_size(layout.name) {
+        Some(size) if size == layout.size => {}
+        Some(size) => mismatches.push(format!(
+            "{}: idl size {} but rust size {}",
+            layout.name, size, layout.size
+        )),
+        None => mismatches.push(format!("{}: idl size is not fixed", layout.name)),
+    }
+    if layout.fields.is_empty() {
+        return mismatches;
+    }
+    let idl_fields = idl.get_fields(layout.name).unwrap_or_default();
+    let idl_names: Vec<&str> = idl_fields.iter().map(|(n, _, _)| n.as_str()).collect();
+    let rust_names: Vec<&str> = layout.fields.iter().map(|(n, _)| *n).collect();
+    if idl_names != rust_names {
+        mismatches.push(format!(
+            "{}: idl fields {:?} but rust fields {:?}",
+            layout.name, idl_names, rust_names
+        ));
+    }
+    for (name, offset, _) in idl_fields.iter() {
+        if let Some((_, rust_offset)) = layout.fields.iter().find(|(n, _)| n == name) {
+            if rust_offset != offset {
+                mismatches.push(format!(
+                    "{}.{}: idl offset {} but rust offset {}",
+                    layout.name, name, offset, rust_offset
+                ));
+            }
+        }
+    }
+    mismatches
+}
+
+#[test]
+fn types_match_idl() {
+    let idl = Idl::load();
+    let layouts = rust_layouts();
+    let mut mismatches: Vec<String> = layouts
+        .iter()
+        .flat_map(|layout| check_layout(&idl, layout))
+        .collect();
+
+    let checked: BTreeSet<&str> = layouts
+        .iter()
+        .map(|l| l.name)
+        .chain(SKIPPED.iter().copied())
+        .collect();
+    for (name, _) in idl.defs.iter() {
+        if !checked.contains(name.as_str()) {
+            mismatches.push(format!("{}: defined in the idl but not checked", name));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "the generated types drifted from idl.json:\n{}",
+        mismatches.join("\n")
+    );
+}
+
+/// the data lengths of the cypher accounts on-chain, including the discriminator
+///
+/// these are derived from the sizes in `idl.json` plus the 8-byte discriminator, not read from deployed accounts, and
+/// are written out so that a change to both the idl and the rust types is still caught.
+const ON_CHAIN_ACCOUNT_LENS: &[(&str, usize)] = &[
+    ("CypherGroup", 10432),
+    ("CypherUser", 1952),
+    ("PriceHistory", 230568),
+];
+
+#[test]
+fn account_sizes_match_on_chain_accounts() {
+    let idl = Idl::load();
+    let group = CypherGroupBuilder::new().build();
+    let user = CypherUserBuilder::new(group.self_address, Pubkey::new_unique()).build();
+    let price_history = price_history(group.self_address, Pubkey::new_unique());
+    let accounts = [
+        (
+            "CypherGroup",
+            CypherGroup::LAYOUT_SIZE,
+            to_account_data(&*group).len(),
+        ),
+        (
+            "CypherUser",
+            CypherUser::LAYOUT_SIZE,
+            to_account_data(&*user).len(),
+        ),
+        (
+            "PriceHistory",
+            PriceHistory::LAYOUT_SIZE,
+            to_account_data(&*price_history).len(),
+        ),
+    ];
+    let mut mismatches = Vec::new();
+    for (name, layout_size, data_len) in accounts {
+        let on_chain_len = ON_CHAIN_ACCOUNT_LENS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, len)| *len)
+            .unwrap_or_else(|| panic!("{} has no known on-chain length", name));
+        if idl.get_def_size(name).map(|size| size + 8) != Some(on_chain_len) {
+            mismatches.push(format!(
+                "{}: idl size {:?} but on-chain length {}",
+                name,
+                idl.get_def_size(name),
+                on_chain_len
+            ));
+        }
+        if layout_size + 8 != on_chain_len {
+            mismatches.push(format!(
+                "{}: rust size {} but on-chain length {}",
+                name, layout_size, on_chain_len
+            ));
+        }
+        if data_len != on_chain_len {
+            mismatches.push(format!(
+                "{}: serialized length {} but on-chain length {}",
+                name, data_len, on_chain_len
+            ));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "the account sizes drifted from the on-chain accounts:\n{}",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn ix_accounts_match_idl() {
+    let idl = Idl::load();
+    let mut mismatches = Vec::new();
+    for ix in idl.instructions.iter() {
+        let name = to_snake_case(ix["name"].as_str().unwrap());
+        let mut expected = Vec::new();
+        flatten_ix_accounts(&ix["accounts"], "", &mut expected);
+        let found: Option<Vec<(String, bool, bool)>> = INSTRUCTIONS
+            .iter()
+            .find(|ix_accounts| ix_accounts.name == name)
+            .map(|ix_accounts| {
+                ix_accounts
+                    .accounts
+                    .iter()
+                    .map(|a| (a.name.to_string(), a.is_writable, a.is_signer))
+                    .collect()
+            });
+        match found {
+            Some(found) if found == expected => {}
+            Some(found) => mismatches.push(format!(
+                "{}: idl accounts {:?} but table accounts {:?}",
+                name, expected, found
+            )),
+            None => mismatches.push(format!("{}: defined in the idl but not in the table", name)),
+        }
+    }
+    if INSTRUCTIONS.len() != idl.instructions.len() {
+        mismatches.push(format!(
+            "the table has {} instructions but the idl has {}",
+            INSTRUCTIONS.len(),
+            idl.instructions.len()
+        ));
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "the instruction account table drifted from idl.json:\n{}",
+        mismatches.join("\n")
+    );
+}
@@ -0,0 +1,533 @@
+//! the accounts of every cypher instruction with their signer and writable flags, as declared in the idl.
+//!
+//! nested accounts are named after their group, e.g. `dex.market`, and listed in the order of the instruction's
+//! account metas, see [`crate::client::WithSigner`] to override the signer flag of an instruction's account.
+use {
+    anchor_discriminator::get_ix_data,
+    anchor_lang::{prelude::*, solana_program::instruction::Instruction},
+    serum_dex::instruction::MarketInstruction,
+};
+
+/// an account of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IxAccount {
+    pub name: &'static str,
+    pub is_writable: bool,
+    pub is_signer: bool,
+}
+
+impl IxAccount {
+    pub const fn readonly(name: &'static str) -> Self {
+        Self {
+            name,
+            is_writable: false,
+            is_signer: false,
+        }
+    }
+
+    pub const fn writable(name: &'static str) -> Self {
+        Self {
+            name,
+            is_writable: true,
+            is_signer: false,
+        }
+    }
+
+    pub const fn signer(name: &'static str) -> Self {
+        Self {
+            name,
+            is_writable: false,
+            is_signer: true,
+        }
+    }
+
+    pub const fn writable_signer(name: &'static str) -> Self {
+        Self {
+            name,
+            is_writable: true,
+            is_signer: true,
+        }
+    }
+
+    /// builds the account meta of the account with the given key
+    pub fn to_account_meta(&self, pubkey: Pubkey) -> AccountMeta {
+        if self.is_writable {
+            AccountMeta::new(pubkey, self.is_signer)
+        } else {
+            AccountMeta::new_readonly(pubkey, self.is_signer)
+        }
+    }
+}
+
+/// the accounts of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IxAccounts {
+    /// the instruction's name in snake case
+    pub name: &'static str,
+    pub accounts: &'static [IxAccount],
+}
+
+impl IxAccounts {
+    /// gets the position of the given account in the instruction's account metas
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.accounts.iter().position(|a| a.name == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'static IxAccount> {
+        self.accounts.iter().find(|a| a.name == name)
+    }
+
+    pub fn signers(&self) -> impl Iterator<Item = &'static IxAccount> {
+        self.accounts.iter().filter(|a| a.is_signer)
+    }
+
+    pub fn writable(&self) -> impl Iterator<Item = &'static IxAccount> {
+        self.accounts.iter().filter(|a| a.is_writable)
+    }
+
+    /// builds the account metas of the instruction from its account keys, in order
+    ///
+    /// returns `None` unless there is exactly one key per account
+    pub fn to_account_metas(&self, pubkeys: &[Pubkey]) -> Option<Vec<AccountMeta>> {
+        if pubkeys.len() != self.accounts.len() {
+            return None;
+        }
+        Some(
+            self.accounts
+                .iter()
+                .zip(pubkeys)
+                .map(|(account, pubkey)| account.to_account_meta(*pubkey))
+                .collect(),
+        )
+    }
+}
+
+/// gets the accounts of the instruction with the given snake case name
+pub fn get_ix_accounts(name: &str) -> Option<&'static IxAccounts> {
+    INSTRUCTIONS.iter().find(|ix| ix.name == name)
+}
+
+/// gets the accounts of the instruction with the given data, built by `client`
+///
+/// the dex instructions routed through cypher are matched by their serum instruction
+pub fn find_ix_accounts(data: &[u8]) -> Option<&'static IxAccounts> {
+    if let Some(discriminator) = data.get(..8) {
+        if let Some(ix) = INSTRUCTIONS
+            .iter()
+            .find(|ix| get_ix_data(ix.name, vec![]) == discriminator)
+        {
+            return Some(ix);
+        }
+    }
+    match MarketInstruction::unpack(data)? {
+        MarketInstruction::InitOpenOrders => Some(&NO_OP_INIT_OPEN_ORDERS),
+        MarketInstruction::CloseOpenOrders => Some(&NO_OP_CLOSE_OPEN_ORDERS),
+        MarketInstruction::NewOrderV3(_) => Some(&NO_OP_NEW_ORDER_V3),
+        MarketInstruction::CancelOrderV2(_)
+        | MarketInstruction::CancelOrderByClientIdV2(_)
+        | MarketInstruction::Prune(_) => Some(&NO_OP_CANCEL_ORDER),
+        MarketInstruction::SettleFunds => Some(&NO_OP_SETTLE_FUNDS),
+        _ => None,
+    }
+}
+
+pub const INIT_CYPHER_GROUP: IxAccounts = IxAccounts {
+    name: "init_cypher_group",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::signer("admin"),
+        IxAccount::writable_signer("payer"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::readonly("quote_mint"),
+        IxAccount::writable("quote_vault"),
+        IxAccount::readonly("rent"),
+        IxAccount::readonly("system_program"),
+        IxAccount::readonly("token_program"),
+        IxAccount::readonly("associated_token_program"),
+    ],
+};
+
+pub const INIT_CYPHER_USER: IxAccounts = IxAccounts {
+    name: "init_cypher_user",
+    accounts: &[
+        IxAccount::readonly("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::writable_signer("owner"),
+        IxAccount::readonly("system_program"),
+    ],
+};
+
+pub const CLOSE_CYPHER_USER: IxAccounts = IxAccounts {
+    name: "close_cypher_user",
+    accounts: &[
+        IxAccount::readonly("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+    ],
+};
+
+pub const CREATE_CYPHER_USER: IxAccounts = IxAccounts {
+    name: "create_cypher_user",
+    accounts: &[
+        IxAccount::readonly("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("owner"),
+        IxAccount::writable_signer("payer"),
+        IxAccount::readonly("system_program"),
+    ],
+};
+
+pub const SET_DELEGATE: IxAccounts = IxAccounts {
+    name: "set_delegate",
+    accounts: &[
+        IxAccount::readonly("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+        IxAccount::readonly("delegate"),
+    ],
+};
+
+pub const INIT_PYTH_PRODUCTS: IxAccounts = IxAccounts {
+    name: "init_pyth_products",
+    accounts: &[
+        IxAccount::readonly("cypher_group"),
+        IxAccount::signer("admin"),
+        IxAccount::writable("pyth_products"),
+    ],
+};
+
+pub const CACHE_ORACLE_PRICE: IxAccounts = IxAccounts {
+    name: "cache_oracle_price",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::readonly("pyth_products"),
+    ],
+};
+
+pub const INIT_MARKET: IxAccounts = IxAccounts {
+    name: "init_market",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::signer("admin"),
+        IxAccount::writable("price_history"),
+        IxAccount::writable("pyth_products"),
+        IxAccount::writable("c_asset_mint"),
+        IxAccount::readonly("pc_mint"),
+        IxAccount::writable("cypher_c_asset_vault"),
+        IxAccount::readonly("dex_market_authority"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::readonly("token_program"),
+        IxAccount::writable("dex.market"),
+        IxAccount::writable("dex.req_q"),
+        IxAccount::writable("dex.event_q"),
+        IxAccount::writable("dex.bids"),
+        IxAccount::writable("dex.asks"),
+        IxAccount::writable("dex.coin_vault"),
+        IxAccount::writable("dex.pc_vault"),
+        IxAccount::readonly("dex.rent"),
+        IxAccount::readonly("dex.dex_program"),
+    ],
+};
+
+pub const NO_OP_INIT_OPEN_ORDERS: IxAccounts = IxAccounts {
+    name: "no_op_init_open_orders",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+        IxAccount::writable_signer("payer"),
+        IxAccount::readonly("dex_market"),
+        IxAccount::readonly("init_oo_authority"),
+        IxAccount::writable("open_orders"),
+        IxAccount::readonly("rent"),
+        IxAccount::readonly("system_program"),
+        IxAccount::readonly("dex_program"),
+    ],
+};
+
+pub const NO_OP_CLOSE_OPEN_ORDERS: IxAccounts = IxAccounts {
+    name: "no_op_close_open_orders",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::writable("user_signer"),
+        IxAccount::readonly("dex_market"),
+        IxAccount::writable("open_orders"),
+        IxAccount::readonly("dex_program"),
+    ],
+};
+
+pub const NO_OP_NEW_ORDER_V3: IxAccounts = IxAccounts {
+    name: "no_op_new_order_v3",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::writable("price_history"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+        IxAccount::readonly("c_asset_mint"),
+        IxAccount::writable("cypher_c_asset_vault"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::writable("dex.market"),
+        IxAccount::writable("dex.open_orders"),
+        IxAccount::writable("dex.req_q"),
+        IxAccount::writable("dex.event_q"),
+        IxAccount::writable("dex.bids"),
+        IxAccount::writable("dex.asks"),
+        IxAccount::writable("dex.coin_vault"),
+        IxAccount::writable("dex.pc_vault"),
+        IxAccount::readonly("dex.vault_signer"),
+        IxAccount::readonly("dex.rent"),
+        IxAccount::readonly("dex.token_program"),
+        IxAccount::readonly("dex.dex_program"),
+    ],
+};
+
+pub const NO_OP_CANCEL_ORDER: IxAccounts = IxAccounts {
+    name: "no_op_cancel_order",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::readonly("user_signer"),
+        IxAccount::readonly("c_asset_mint"),
+        IxAccount::writable("cypher_c_asset_vault"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::writable("dex.market"),
+        IxAccount::readonly("dex.prune_authority"),
+        IxAccount::writable("dex.bids"),
+        IxAccount::writable("dex.asks"),
+        IxAccount::writable("dex.open_orders"),
+        IxAccount::writable("dex.event_q"),
+        IxAccount::writable("dex.coin_vault"),
+        IxAccount::writable("dex.pc_vault"),
+        IxAccount::readonly("dex.vault_signer"),
+        IxAccount::readonly("dex.token_program"),
+        IxAccount::readonly("dex.dex_program"),
+    ],
+};
+
+pub const NO_OP_SETTLE_FUNDS: IxAccounts = IxAccounts {
+    name: "no_op_settle_funds",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::readonly("user_signer"),
+        IxAccount::readonly("c_asset_mint"),
+        IxAccount::writable("cypher_c_asset_vault"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::writable("dex.market"),
+        IxAccount::writable("dex.open_orders"),
+        IxAccount::writable("dex.coin_vault"),
+        IxAccount::writable("dex.pc_vault"),
+        IxAccount::readonly("dex.vault_signer"),
+        IxAccount::readonly("dex.token_program"),
+        IxAccount::readonly("dex.dex_program"),
+    ],
+};
+
+pub const UPDATE_MARKET_EXPIRATION_TIME: IxAccounts = IxAccounts {
+    name: "update_market_expiration_time",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::signer("admin"),
+    ],
+};
+
+pub const EXECUTE_MARKET: IxAccounts = IxAccounts {
+    name: "execute_market",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::signer("admin"),
+        IxAccount::readonly("dex_market"),
+        IxAccount::readonly("event_q"),
+    ],
+};
+
+pub const CLOSE_MARKET: IxAccounts = IxAccounts {
+    name: "close_market",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::signer("admin"),
+        IxAccount::writable_signer("fee_receiver"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::writable("price_history"),
+        IxAccount::writable("pyth_products"),
+        IxAccount::writable("c_asset_mint"),
+        IxAccount::writable("cypher_c_asset_vault"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::readonly("token_program"),
+    ],
+};
+
+pub const DEPOSIT_COLLATERAL: IxAccounts = IxAccounts {
+    name: "deposit_collateral",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::writable("deposit_from"),
+        IxAccount::readonly("token_program"),
+    ],
+};
+
+pub const WITHDRAW_COLLATERAL: IxAccounts = IxAccounts {
+    name: "withdraw_collateral",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::writable("withdraw_to"),
+        IxAccount::readonly("token_program"),
+    ],
+};
+
+pub const LIQUIDATE_COLLATERAL: IxAccounts = IxAccounts {
+    name: "liquidate_collateral",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::signer("user_signer"),
+        IxAccount::writable("liqee_cypher_user"),
+    ],
+};
+
+pub const SETTLE_POSITION: IxAccounts = IxAccounts {
+    name: "settle_position",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::writable("cypher_user"),
+        IxAccount::readonly("c_asset_mint"),
+    ],
+};
+
+pub const UPDATE_QUOTE_TOKEN_INDEX: IxAccounts = IxAccounts {
+    name: "update_quote_token_index",
+    accounts: &[IxAccount::writable("cypher_group")],
+};
+
+pub const SWEEP_FEE: IxAccounts = IxAccounts {
+    name: "sweep_fee",
+    accounts: &[
+        IxAccount::writable("cypher_group"),
+        IxAccount::signer("admin"),
+        IxAccount::readonly("vault_signer"),
+        IxAccount::writable("cypher_pc_vault"),
+        IxAccount::writable("withdraw_to"),
+        IxAccount::readonly("token_program"),
+    ],
+};
+
+/// the accounts of every instruction of the idl, in idl order
+pub const INSTRUCTIONS: &[IxAccounts] = &[
+    INIT_CYPHER_GROUP,
+    INIT_CYPHER_USER,
+    CLOSE_CYPHER_USER,
+    CREATE_CYPHER_USER,
+    SET_DELEGATE,
+    INIT_PYTH_PRODUCTS,
+    CACHE_ORACLE_PRICE,
+    INIT_MARKET,
+    NO_OP_INIT_OPEN_ORDERS,
+    NO_OP_CLOSE_OPEN_ORDERS,
+    NO_OP_NEW_ORDER_V3,
+    NO_OP_CANCEL_ORDER,
+    NO_OP_SETTLE_FUNDS,
+    UPDATE_MARKET_EXPIRATION_TIME,
+    EXECUTE_MARKET,
+    CLOSE_MARKET,
+    DEPOSIT_COLLATERAL,
+    WITHDRAW_COLLATERAL,
+    LIQUIDATE_COLLATERAL,
+    SETTLE_POSITION,
+    UPDATE_QUOTE_TOKEN_INDEX,
+    SWEEP_FEE,
+];
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            client::{deposit_collateral_ix, settle_funds_ix, WithSigner},
+            signer::{SignerError, UserSigner},
+        },
+    };
+
+    fn keys(n: usize) -> Vec<Pubkey> {
+        (0..n).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn table_matches_builder_account_metas() {
+        let k = keys(5);
+        let ix = deposit_collateral_ix(&k[0], &k[1], &k[2], &UserSigner::Owner(k[3]), &k[4], 100);
+        let ix_accounts = find_ix_accounts(&ix.data).unwrap();
+        assert_eq!(ix_accounts, &DEPOSIT_COLLATERAL);
+        let pubkeys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(ix_accounts.to_account_metas(&pubkeys), Some(ix.accounts));
+
+        let k = keys(11);
+        let ix = settle_funds_ix(
+            &k[0],
+            &k[1],
+            &k[2],
+            &UserSigner::Owner(k[3]),
+            &k[4],
+            &k[5],
+            &k[6],
+            &k[7],
+            &k[8],
+            &k[9],
+            &k[10],
+        );
+        assert_eq!(find_ix_accounts(&ix.data), Some(&NO_OP_SETTLE_FUNDS));
+        let pubkeys: Vec<Pubkey> = ix.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(
+            NO_OP_SETTLE_FUNDS.to_account_metas(&pubkeys),
+            Some(ix.accounts)
+        );
+        assert_eq!(NO_OP_SETTLE_FUNDS.position("dex.open_orders"), Some(8));
+    }
+
+    #[test]
+    fn overrides_signer() {
+        let k = keys(11);
+        let ix = settle_funds_ix(
+            &k[0],
+            &k[1],
+            &k[2],
+            &UserSigner::Owner(k[3]),
+            &k[4],
+            &k[5],
+            &k[6],
+            &k[7],
+            &k[8],
+            &k[9],
+            &k[10],
+        );
+        let idx = NO_OP_SETTLE_FUNDS.position("user_signer").unwrap();
+        assert!(!ix.accounts[idx].is_signer);
+        let ix = ix.with_signer("user_signer", true).unwrap();
+        assert!(ix.accounts[idx].is_signer);
+        assert_eq!(
+            ix.accounts[idx].is_writable,
+            NO_OP_SETTLE_FUNDS.accounts[idx].is_writable
+        );
+
+        assert_eq!(
+            ix.clone().with_signer("owner", true).err(),
+            Some(SignerError::UnknownAccount("no_op_settle_funds"))
+        );
+        let mut unknown = ix;
+        unknown.data = vec![];
+        assert_eq!(
+            unknown.with_signer("user_signer", true).err(),
+            Some(SignerError::UnknownInstruction)
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! size and offset checks of the zero-copy layouts against the bundled idl, and layout versions for decoding.
+//!
+//! the sizes below are written out by hand from `idl.json`, a mismatch means the generated types drifted from the
+//! idl and fails the build instead of silently corrupting decoding. the field offsets are only asserted in tests, as
+//! `offset_of!` needs a newer compiler than the bpf toolchain.
+use {
+    crate::{
+        CypherGroup, CypherGroupConfig, CypherMarket, CypherMarketConfig, CypherToken,
+        CypherTokenConfig, CypherUser, OpenOrdersInfo, OraclePrice, PriceHistory,
+        PriceHistoryConfig, PriceWithTs, UserPosition,
+    },
+    anchor_lang::{prelude::*, ZeroCopy},
+    bytemuck::{bytes_of_mut, from_bytes, Zeroable},
+    static_assertions::const_assert_eq,
+    std::mem::size_of,
+};
+
+/// the size of a `CypherGroup`, excluding the discriminator
+pub const CYPHER_GROUP_SIZE: usize = 10424;
+/// the size of a `CypherGroupConfig`
+pub const CYPHER_GROUP_CONFIG_SIZE: usize = 32;
+/// the size of a `CypherMarket`
+pub const CYPHER_MARKET_SIZE: usize = 312;
+/// the size of a `CypherMarketConfig`
+pub const CYPHER_MARKET_CONFIG_SIZE: usize = 8;
+/// the size of a `CypherToken`
+pub const CYPHER_TOKEN_SIZE: usize = 344;
+/// the size of a `CypherTokenConfig`
+pub const CYPHER_TOKEN_CONFIG_SIZE: usize = 16;
+/// the size of a `CypherUser`, excluding the discriminator
+pub const CYPHER_USER_SIZE: usize = 1944;
+/// the size of a `UserPosition`
+pub const USER_POSITION_SIZE: usize = 112;
+/// the size of an `OpenOrdersInfo`
+pub const OPEN_ORDERS_INFO_SIZE: usize = 48;
+/// the size of an `OraclePrice`
+pub const ORACLE_PRICE_SIZE: usize = 16;
+/// the size of a `PriceHistory`, excluding the discriminator
+pub const PRICE_HISTORY_ACCOUNT_SIZE: usize = 230560;
+/// the size of a `PriceHistoryConfig`
+pub const PRICE_HISTORY_CONFIG_SIZE: usize = 24;
+/// the size of a `PriceWithTs`
+pub const PRICE_WITH_TS_SIZE: usize = 16;
+
+const_assert_eq!(size_of::<CypherGroup>(), CYPHER_GROUP_SIZE);
+const_assert_eq!(size_of::<CypherGroupConfig>(), CYPHER_GROUP_CONFIG_SIZE);
+const_assert_eq!(size_of::<CypherMarket>(), CYPHER_MARKET_SIZE);
+const_assert_eq!(size_of::<CypherMarketConfig>(), CYPHER_MARKET_CONFIG_SIZE);
+const_assert_eq!(size_of::<CypherToken>(), CYPHER_TOKEN_SIZE);
+const_assert_eq!(size_of::<CypherTokenConfig>(), CYPHER_TOKEN_CONFIG_SIZE);
+const_assert_eq!(size_of::<CypherUser>(), CYPHER_USER_SIZE);
+const_assert_eq!(size_of::<UserPosition>(), USER_POSITION_SIZE);
+const_assert_eq!(size_of::<OpenOrdersInfo>(), OPEN_ORDERS_INFO_SIZE);
+const_assert_eq!(size_of::<OraclePrice>(), ORACLE_PRICE_SIZE);
+const_assert_eq!(size_of::<PriceHistory>(), PRICE_HISTORY_ACCOUNT_SIZE);
+const_assert_eq!(size_of::<PriceHistoryConfig>(), PRICE_HISTORY_CONFIG_SIZE);
+const_assert_eq!(size_of::<PriceWithTs>(), PRICE_WITH_TS_SIZE);
+
+/// the layout of a cypher account, relative to the layout of the bundled idl
+///
+/// this assumes cypher only ever appends fields to its accounts, so that an older layout is a prefix of the current
+/// one and the current one is a prefix of a newer layout, the program's source is not bundled to verify it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutVersion {
+    /// an older layout of the given size, excluding the discriminator, the fields it lacks are decoded as zeroes
+    Older(usize),
+    /// the layout of the bundled idl
+    Current,
+    /// a newer layout of the given size, excluding the discriminator, the fields it appends are ignored
+    Newer(usize),
+}
+
+/// an error decoding a versioned account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutError {
+    /// the account data does not start with the account's discriminator
+    InvalidDiscriminator,
+    /// the account data has nothing after the discriminator
+    EmptyAccount,
+    /// the account data has a layout which can not be borrowed as this crate's account types
+    UnsupportedVersion(LayoutVersion),
+}
+
+/// a zero-copy account whose size differs between layout versions
+pub trait VersionedAccount: ZeroCopy + Owner {
+    /// the account's size in the current layout, excluding the discriminator
+    const LAYOUT_SIZE: usize;
+
+    /// detects the layout of the given account data, including the discriminator
+    fn detect_layout(data: &[u8]) -> std::result::Result<LayoutVersion, LayoutError> {
+        if data.len() < 8 || data[..8] != Self::discriminator() {
+            return Err(LayoutError::InvalidDiscriminator);
+        }
+        let size = data.len() - 8;
+        if size == 0 {
+            return Err(LayoutError::EmptyAccount);
+        }
+        Ok(match size.cmp(&Self::LAYOUT_SIZE) {
+            std::cmp::Ordering::Less => LayoutVersion::Older(size),
+            std::cmp::Ordering::Equal => LayoutVersion::Current,
+            std::cmp::Ordering::Greater => LayoutVersion::Newer(size),
+        })
+    }
+
+    /// borrows the given account data without copying, failing unless it has the current layout
+    fn decode_versioned(data: &[u8]) -> std::result::Result<&Self, LayoutError> {
+        match Self::detect_layout(data)? {
+            LayoutVersion::Current => Ok(from_bytes::<Self>(&data[8..])),
+            version => Err(LayoutError::UnsupportedVersion(version)),
+        }
+    }
+
+    /// copies the given account data of any layout onto the heap, zeroing the fields an older layout lacks and
+    /// ignoring the fields a newer layout appends
+    fn decode_compatible(data: &[u8]) -> std::result::Result<Box<Self>, LayoutError> {
+        Self::detect_layout(data)?;
+        let data = &data[8..];
+        let len = data.len().min(Self::LAYOUT_SIZE);
+        let mut account = Box::new(Self::zeroed());
+        bytes_of_mut(&mut *account)[..len].copy_from_slice(&data[..len]);
+        Ok(account)
+    }
+}
+
+impl VersionedAccount for CypherGroup {
+    const LAYOUT_SIZE: usize = CYPHER_GROUP_SIZE;
+}
+
+impl VersionedAccount for CypherUser {
+    const LAYOUT_SIZE: usize = CYPHER_USER_SIZE;
+}
+
+impl VersionedAccount for PriceHistory {
+    const LAYOUT_SIZE: usize = PRICE_HISTORY_ACCOUNT_SIZE;
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*, std::mem::offset_of};
+
+    macro_rules! const_assert_offsets {
+        ($ty:ty { $($field:ident: $offset:expr),* $(,)? }) => {
+            $(const_assert_eq!(offset_of!($ty, $field), $offset);)*
+        };
+    }
+
+    const_assert_offsets!(CypherGroup {
+        self_address: 0,
+        admin: 32,
+        vault_signer: 64,
+        insurance_fund: 96,
+        markets: 104,
+        tokens: 4784,
+        config: 10288,
+        vault_signer_bump_seed: 10320,
+        padding1: 10321,
+        protocol_fee: 10328,
+        padding2: 10336,
+    });
+    const_assert_offsets!(CypherGroupConfig {
+        margin_init_ratio: 0,
+        margin_maint_ratio: 2,
+        margin_partial_ratio: 4,
+        liquidator_bonus_bps: 6,
+        liquidation_insurance_fee_bps: 8,
+        maker_fee_bps: 10,
+        taker_fee_bps: 12,
+        fee_percentage_for_protocol: 14,
+        fee_percentage_for_insurance_fund: 15,
+        padding: 16,
+    });
+    const_assert_offsets!(CypherMarket {
+        dex_market: 0,
+        price_history: 32,
+        pyth_products: 64,
+        oracle_price: 96,
+        market_price: 112,
+        positions_count: 120,
+        listed_at: 128,
+        expires_at: 136,
+        market_type: 144,
+        is_executed: 145,
+        dex_market_authority_bump_seed: 146,
+        padding1: 147,
+        padding2: 152,
+        padding3: 232,
+    });
+    const_assert_offsets!(CypherMarketConfig {
+        mint_init_ratio: 0,
+        mint_maint_ratio: 2,
+        mint_partial_ratio: 4,
+        padding: 6,
+    });
+    const_assert_offsets!(CypherToken {
+        mint: 0,
+        vault: 32,
+        base_deposits: 64,
+        base_borrows: 88,
+        deposit_index: 112,
+        borrow_index: 136,
+        accum_deposit_interest_payment: 160,
+        accum_borrow_interest_payment: 184,
+        index_updated_at: 208,
+        accum_borrows: 216,
+        accum_repays: 224,
+        config: 232,
+        padding: 248,
+    });
+    const_assert_offsets!(CypherTokenConfig {
+        optimal_util: 0,
+        optimal_apr: 2,
+        max_apr: 4,
+        decimals: 6,
+        padding: 7,
+    });
+    const_assert_offsets!(CypherUser {
+        cypher_group: 0,
+        user_signer: 32,
+        positions: 64,
+        authority_bump_seed: 1856,
+        padding1: 1857,
+        delegate: 1864,
+        padding2: 1896,
+    });
+    const_assert_offsets!(UserPosition {
+        base_deposits: 0,
+        base_borrows: 24,
+        oo_info: 48,
+        market_idx: 96,
+        padding: 97,
+        padding2: 104,
+    });
+    const_assert_offsets!(OpenOrdersInfo {
+        coin_total: 0,
+        coin_free: 8,
+        pc_total: 16,
+        pc_free: 24,
+        referrer_rebates_accrued: 32,
+        is_account_open: 40,
+        padding: 41,
+    });
+    const_assert_offsets!(OraclePrice {
+        price: 0,
+        cached_slot: 8,
+    });
+    const_assert_offsets!(PriceHistory {
+        cypher_group: 0,
+        c_asset_mint: 32,
+        data: 64,
+        config: 230464,
+        longer_head: 230488,
+        shorter_head: 230490,
+        tail: 230492,
+        padding1: 230494,
+        padding2: 230496,
+    });
+    const_assert_offsets!(PriceHistoryConfig {
+        longer_time_horizon: 0,
+        shorter_time_horizon: 8,
+        price_collection_tick: 16,
+    });
+    const_assert_offsets!(PriceWithTs {
+        price: 0,
+        timestamp: 8,
+    });
+
+    fn user_data() -> Vec<u8> {
+        let user = CypherUserBuilder::new(Pubkey::new_unique(), Pubkey::new_unique())
+            .delegate(Pubkey::new_unique())
+            .build();
+        to_account_data(&*user)
+    }
+
+    #[test]
+    fn decodes_current_layout() {
+        let data = user_data();
+        assert_eq!(CypherUser::detect_layout(&data), Ok(LayoutVersion::Current));
+        let user = CypherUser::decode_versioned(&data).unwrap();
+        let copied = CypherUser::decode_compatible(&data).unwrap();
+        assert_eq!(bytemuck::bytes_of(user), bytemuck::bytes_of(&*copied));
+        assert_eq!(
+            CypherGroup::detect_layout(&data),
+            Err(LayoutError::InvalidDiscriminator)
+        );
+        assert_eq!(
+            CypherUser::detect_layout(&data[..8]),
+            Err(LayoutError::EmptyAccount)
+        );
+    }
+
+    #[test]
+    fn decodes_older_layout_with_zeroed_fields() {
+        let data = user_data();
+        let older = &data[..8 + offset_of!(CypherUser, delegate)];
+        let version = LayoutVersion::Older(offset_of!(CypherUser, delegate));
+        assert_eq!(CypherUser::detect_layout(older), Ok(version));
+        assert_eq!(
+            CypherUser::decode_versioned(older).err(),
+            Some(LayoutError::UnsupportedVersion(version))
+        );
+
+        let expected = CypherUser::decode_versioned(&data).unwrap();
+        let user = CypherUser::decode_compatible(older).unwrap();
+        assert_eq!(user.cypher_group, expected.cypher_group);
+        assert_eq!(user.user_signer, expected.user_signer);
+        assert_eq!(user.delegate, Pubkey::default());
+    }
+
+    #[test]
+    fn decodes_newer_layout_ignoring_appended_fields() {
+        let mut data = user_data();
+        let expected = CypherUser::decode_compatible(&data).unwrap();
+        data.extend_from_slice(&[1; 64]);
+        let version = LayoutVersion::Newer(CYPHER_USER_SIZE + 64);
+        assert_eq!(CypherUser::detect_layout(&data), Ok(version));
+        assert_eq!(
+            CypherUser::decode_versioned(&data).err(),
+            Some(LayoutError::UnsupportedVersion(version))
+        );
+
+        let user = CypherUser::decode_compatible(&data).unwrap();
+        assert_eq!(bytemuck::bytes_of(&*user), bytemuck::bytes_of(&*expected));
+    }
+}
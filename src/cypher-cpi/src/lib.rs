@@ -1,12 +1,43 @@
+pub mod basis;
+pub mod book_diff;
 pub mod client;
 pub mod constants;
+pub mod display;
+pub mod fees;
+pub mod fill_sim;
+#[cfg(test)]
+mod idl_check;
+pub mod ix_accounts;
+pub mod layout;
+pub mod liquidator;
+pub mod loader;
+pub mod margin_cpi;
+pub mod order_lookup;
+pub mod orders;
+pub mod pnl;
+pub mod portfolio;
+pub mod preflight;
+pub mod risk;
 pub mod serum_cpi;
 pub mod serum_slab;
+pub mod settlement;
+pub mod signer;
+#[cfg(feature = "client")]
+pub mod snapshot;
+pub mod sub_accounts;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub mod utils;
+#[cfg(feature = "serde")]
+pub mod views;
+
+#[cfg(test)]
+mod tests;
 
 use anchor_lang::prelude::*;
 use constants::*;
 use jet_proto_math::Number;
+use signer::{UserAction, UserSigner};
 use std::mem::take;
 
 anchor_gen::generate_cpi_interface!(
@@ -20,11 +51,21 @@ anchor_gen::generate_cpi_interface!(
         CypherToken,
         CypherUser,
         UserPosition,
-        OpenOrdersInfo,
-        PriceHistory
+        OpenOrdersInfo
     )
 );
 
+/// an error computing a user's margin values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginError {
+    /// the group has no quote token
+    QuoteTokenNotListed,
+    /// the user has a non-empty position in a market which is not listed in the group
+    MarketNotListed(usize),
+}
+
+pub type MarginResult<T> = std::result::Result<T, MarginError>;
+
 #[cfg(feature = "mainnet-beta")]
 declare_id!("CYPHER3ziDd1rasgBcGGbx4fMtSS72x6NEM5Zvx2vNmK");
 #[cfg(not(feature = "mainnet-beta"))]
@@ -66,17 +107,14 @@ impl CypherGroup {
 
     /// gets the cypher token at the given index
     pub fn get_cypher_token(&self, token_index: usize) -> Option<&CypherToken> {
-        if self.tokens[token_index].mint == Pubkey::default() {
-            return None;
-        }
-        self.tokens.get(token_index)
+        self.tokens
+            .get(token_index)
+            .filter(|token| token.mint != Pubkey::default())
     }
 
     /// gets the cypher market at the given index
     pub fn get_cypher_market(&self, market_index: usize) -> Option<&CypherMarket> {
-        if self.tokens[market_index].mint == Pubkey::default() {
-            return None;
-        }
+        self.get_cypher_token(market_index)?;
         self.markets.get(market_index)
     }
 
@@ -144,6 +182,16 @@ impl CypherToken {
     pub fn total_borrows(&self) -> Number {
         self.base_borrows() * self.borrow_index()
     }
+
+    /// gets the token's utilization, the total borrows over the total deposits
+    pub fn utilization(&self) -> Number {
+        let total_deposits = self.total_deposits();
+        if total_deposits == Number::ZERO {
+            Number::ZERO
+        } else {
+            self.total_borrows() / total_deposits
+        }
+    }
 }
 
 impl CypherMarket {
@@ -151,6 +199,95 @@ impl CypherMarket {
     pub fn oracle_price(&self) -> u64 {
         self.oracle_price.price
     }
+
+    /// gets the name of the market's type
+    pub fn market_type_name(&self) -> &'static str {
+        let market_type = self.market_type;
+        match market_type {
+            MarketType::PairFuture => "PairFuture",
+            MarketType::IndexFuture => "IndexFuture",
+        }
+    }
+
+    /// checks whether the market has an expiry and it has passed at the given unix timestamp
+    pub fn is_expired(&self, now: u64) -> bool {
+        let expires_at = self.expires_at;
+        expires_at != 0 && now >= expires_at
+    }
+
+    /// gets the seconds left until the market expires, `None` if it has no expiry or it has already passed
+    pub fn time_to_expiry(&self, now: u64) -> Option<u64> {
+        let expires_at = self.expires_at;
+        if expires_at == 0 || now >= expires_at {
+            None
+        } else {
+            Some(expires_at - now)
+        }
+    }
+
+    /// checks whether the market expired but has not been executed yet
+    pub fn needs_execution(&self, now: u64) -> bool {
+        self.is_expired(now) && !self.is_executed
+    }
+}
+
+impl PriceHistory {
+    /// gets the price at the given index of the price history's buffer
+    ///
+    /// the entries are read unaligned since `PriceWithTs` is not packed
+    pub fn get_price(&self, idx: usize) -> Option<PriceWithTs> {
+        if idx >= PRICE_HISTORY_SIZE {
+            return None;
+        }
+        let data = std::ptr::addr_of!(self.data) as *const PriceWithTs;
+        Some(unsafe { data.add(idx).read_unaligned() })
+    }
+
+    /// iterates over the price history's buffer, skipping unused entries
+    pub fn iter_prices(&self) -> impl Iterator<Item = PriceWithTs> + '_ {
+        (0..PRICE_HISTORY_SIZE)
+            .filter_map(move |idx| self.get_price(idx))
+            .filter(|p| p.timestamp != 0)
+    }
+
+    /// iterates over the prices from `head` to the tail, the most recently added price, wrapping around the buffer
+    fn iter_window(&self, head: u16) -> impl Iterator<Item = PriceWithTs> + '_ {
+        let head = head as usize;
+        let tail = self.tail as usize;
+        let len = (tail + PRICE_HISTORY_SIZE - head) % PRICE_HISTORY_SIZE + 1;
+        (0..len)
+            .filter_map(move |i| self.get_price((head + i) % PRICE_HISTORY_SIZE))
+            .filter(|p| p.timestamp != 0)
+    }
+
+    /// gets the average of the prices in the window starting at `head`, `None` if the window is empty
+    ///
+    /// prices are collected every `price_collection_tick`, so their average is time-weighted
+    fn get_window_twap(&self, head: u16) -> Option<Number> {
+        let (sum, count) = self
+            .iter_window(head)
+            .fold((0_u128, 0_u64), |(sum, count), p| {
+                (sum + p.price as u128, count + 1)
+            });
+        if count == 0 {
+            return None;
+        }
+        let count = count as u128;
+        Some(
+            Number::from((sum / count) as u64)
+                + Number::from((sum % count) as u64) / Number::from(count as u64),
+        )
+    }
+
+    /// gets the twap over the longer time horizon
+    pub fn longer_twap(&self) -> Option<Number> {
+        self.get_window_twap(self.longer_head)
+    }
+
+    /// gets the twap over the shorter time horizon
+    pub fn shorter_twap(&self) -> Option<Number> {
+        self.get_window_twap(self.shorter_head)
+    }
 }
 
 impl CypherUser {
@@ -179,6 +316,24 @@ impl CypherUser {
         }
     }
 
+    /// gets the signer for the given key, `None` if the key is neither the owner nor the delegate
+    pub fn get_signer(&self, key: &Pubkey) -> Option<UserSigner> {
+        if *key == self.user_signer {
+            Some(UserSigner::Owner(*key))
+        } else if *key == self.delegate && self.delegate != Pubkey::default() {
+            Some(UserSigner::Delegate(*key))
+        } else {
+            None
+        }
+    }
+
+    /// checks whether the given key may perform the given action on this user
+    pub fn can_sign(&self, key: &Pubkey, action: UserAction) -> bool {
+        self.get_signer(key)
+            .map(|signer| signer.can_perform(action))
+            .unwrap_or(false)
+    }
+
     fn get_position_idx(&self, token_idx: usize) -> Option<usize> {
         if token_idx == QUOTE_TOKEN_IDX {
             Some(QUOTE_TOKEN_IDX)
@@ -199,9 +354,28 @@ impl CypherUser {
         }
     }
 
+    /// gets the market price for the given position, `None` if the position is empty and its market is no longer listed
+    ///
+    /// a non-empty position in a market which is no longer listed is a `MarginError::MarketNotListed`, it used to be
+    /// skipped, which left it out of the margin values
+    fn get_position_market_price(
+        &self,
+        group: &CypherGroup,
+        position: &UserPosition,
+    ) -> MarginResult<Option<u64>> {
+        let market_idx = position.market_idx as usize;
+        match group.get_cypher_market(market_idx) {
+            Some(market) => Ok(Some(market.market_price)),
+            None if position.is_empty() => Ok(None),
+            None => Err(MarginError::MarketNotListed(market_idx)),
+        }
+    }
+
     /// gets the users's assets value
-    pub fn get_assets_value(&self, group: &CypherGroup) -> Number {
-        let quote_token = group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+    pub fn get_assets_value(&self, group: &CypherGroup) -> MarginResult<Number> {
+        let quote_token = group
+            .get_cypher_token(QUOTE_TOKEN_IDX)
+            .ok_or(MarginError::QuoteTokenNotListed)?;
         let quote_position = self.get_position(QUOTE_TOKEN_IDX);
         let quote_deposits = if let Some(position) = quote_position {
             position.total_deposits(quote_token)
@@ -211,12 +385,9 @@ impl CypherUser {
         let mut assets_value = quote_deposits;
 
         for position in self.iter_positions() {
-            let market_idx = position.market_idx as usize;
-            let market = group.get_cypher_market(market_idx);
-            let market_price = if let Some(m) = market {
-                m.market_price
-            } else {
-                continue;
+            let market_price = match self.get_position_market_price(group, position)? {
+                Some(market_price) => market_price,
+                None => continue,
             };
             let oo_info = &position.oo_info;
             if oo_info.is_account_open {
@@ -226,12 +397,14 @@ impl CypherUser {
             }
             assets_value += position.base_deposits() * market_price;
         }
-        assets_value
+        Ok(assets_value)
     }
 
     /// gets the users's liabilities value
-    pub fn get_liabilities_value(&self, group: &CypherGroup) -> Number {
-        let quote_token = group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap();
+    pub fn get_liabilities_value(&self, group: &CypherGroup) -> MarginResult<Number> {
+        let quote_token = group
+            .get_cypher_token(QUOTE_TOKEN_IDX)
+            .ok_or(MarginError::QuoteTokenNotListed)?;
         let quote_position = self.get_position(QUOTE_TOKEN_IDX);
         let quote_borrows = if let Some(position) = quote_position {
             position.total_borrows(quote_token)
@@ -241,70 +414,124 @@ impl CypherUser {
         let mut liabs_value = quote_borrows;
 
         for position in self.iter_positions() {
-            let market_idx = position.market_idx as usize;
-            let market = group.get_cypher_market(market_idx);
-            let market_price = if let Some(m) = market {
-                m.market_price
-            } else {
-                continue;
+            let market_price = match self.get_position_market_price(group, position)? {
+                Some(market_price) => market_price,
+                None => continue,
             };
             liabs_value += position.base_borrows() * market_price;
         }
-        liabs_value
+        Ok(liabs_value)
     }
 
-    /// gets the user's margin c-ratio
-    pub fn get_margin_c_ratio(&self, group: &CypherGroup) -> Number {
-        let liabs_value = self.get_liabilities_value(group);
-        if liabs_value == Number::ZERO {
-            Number::MAX
+    /// gets the user's margin values, computed in a single pass over the positions
+    pub fn get_margin_values(&self, group: &CypherGroup) -> MarginResult<MarginValues> {
+        let quote_token = group
+            .get_cypher_token(QUOTE_TOKEN_IDX)
+            .ok_or(MarginError::QuoteTokenNotListed)?;
+        let quote_position = self.get_position(QUOTE_TOKEN_IDX);
+        let (quote_deposits, quote_borrows) = if let Some(position) = quote_position {
+            (
+                position.total_deposits(quote_token),
+                position.total_borrows(quote_token),
+            )
         } else {
-            let assets_value = self.get_assets_value(group);
-            assets_value / liabs_value
+            (Number::ZERO, Number::ZERO)
+        };
+        let mut values = MarginValues {
+            assets_value: quote_deposits,
+            liabilities_value: quote_borrows,
+            largest_deposit_value: quote_deposits,
+            lowest_borrow_price: if quote_borrows > Number::ZERO {
+                Some(1)
+            } else {
+                None
+            },
+        };
+
+        for position in self.iter_positions() {
+            let market_price = match self.get_position_market_price(group, position)? {
+                Some(market_price) => market_price,
+                None => continue,
+            };
+            let oo_info = &position.oo_info;
+            if oo_info.is_account_open {
+                let oo_coin_value = oo_info.coin_total * market_price;
+                let oo_value = oo_coin_value + oo_info.pc_total + oo_info.referrer_rebates_accrued;
+                values.assets_value += oo_value.into();
+            }
+            // we can use native deposits here because cAssets don't accrue interest
+            let deposit_value = position.base_deposits() * market_price;
+            values.assets_value += deposit_value;
+            values.largest_deposit_value = Number::max(values.largest_deposit_value, deposit_value);
+            if position.base_borrows() > Number::ZERO {
+                values.liabilities_value += position.base_borrows() * market_price;
+                values.lowest_borrow_price = Some(
+                    values
+                        .lowest_borrow_price
+                        .map_or(market_price, |p| p.min(market_price)),
+                );
+            }
         }
+        Ok(values)
+    }
+
+    /// gets the user's margin c-ratio, `Number::MAX` if the user has no liabilities
+    pub fn get_margin_c_ratio(&self, group: &CypherGroup) -> MarginResult<Number> {
+        Ok(self.get_margin_values(group)?.c_ratio())
     }
 
     /// gets the user's margin c-ratio components
     /// the first number is the margin c-ratio, the second number is the assets value and the third  number is the liabilites value
-    pub fn get_margin_c_ratio_components(&self, group: &CypherGroup) -> (Number, Number, Number) {
-        let liabs_value = self.get_liabilities_value(group);
-        if liabs_value == Number::ZERO {
-            (Number::MAX, self.get_assets_value(group), liabs_value)
-        } else {
-            let assets_value = self.get_assets_value(group);
-            (assets_value / liabs_value, assets_value, liabs_value)
-        }
+    pub fn get_margin_c_ratio_components(
+        &self,
+        group: &CypherGroup,
+    ) -> MarginResult<(Number, Number, Number)> {
+        let values = self.get_margin_values(group)?;
+        Ok((
+            values.c_ratio(),
+            values.assets_value,
+            values.liabilities_value,
+        ))
     }
 
     /// checks if the user is bankrupt
-    pub fn is_bankrupt(&self, group: &CypherGroup) -> bool {
-        let quote_position = self.get_position(QUOTE_TOKEN_IDX).unwrap();
-        let mut largest_deposit_value =
-            quote_position.total_deposits(group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap());
-        let mut lowest_borrow_price = if quote_position.base_borrows() > Number::ZERO {
-            1_u64
-        } else {
-            u64::MAX
-        };
-        for position in self.iter_positions() {
-            let market_idx = position.market_idx as usize;
-            let market_price = group.get_cypher_market(market_idx).unwrap().market_price;
-            // we can use native deposits here because cAssets don't accrue interest
-            let deposit_value = position.base_deposits() * market_price;
-            largest_deposit_value = Number::max(largest_deposit_value, deposit_value);
-            if position.base_borrows() > Number::ZERO {
-                lowest_borrow_price = lowest_borrow_price.min(market_price);
-            }
-        }
+    pub fn is_bankrupt(&self, group: &CypherGroup) -> MarginResult<bool> {
+        Ok(self.get_margin_values(group)?.is_bankrupt(group))
+    }
+}
 
-        if lowest_borrow_price == u64::MAX {
-            return false;
+/// the margin values of a user, see [`CypherUser::get_margin_values`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarginValues {
+    pub assets_value: Number,
+    pub liabilities_value: Number,
+    /// the value of the user's largest deposit, open orders excluded
+    pub largest_deposit_value: Number,
+    /// the lowest price of the user's borrowed tokens, the quote token's price is one, `None` if the user has no borrows
+    pub lowest_borrow_price: Option<u64>,
+}
+
+impl MarginValues {
+    /// gets the margin c-ratio, `Number::MAX` if there are no liabilities
+    pub fn c_ratio(&self) -> Number {
+        if self.liabilities_value == Number::ZERO {
+            Number::MAX
+        } else {
+            self.assets_value / self.liabilities_value
         }
+    }
 
+    /// checks if no deposit can cover the liquidation of a single native unit of the cheapest borrow
+    pub fn is_bankrupt(&self, group: &CypherGroup) -> bool {
+        let lowest_borrow_price = match self.lowest_borrow_price {
+            Some(price) => price,
+            None => return false,
+        };
         let liq_fee = group.liq_liqor_fee() + group.liq_insurance_fee();
-        let collateral_for_min_borrow_unit = (liq_fee * lowest_borrow_price).as_u64_ceil(0);
+        // compared as numbers, the values can be larger than a `u64`
+        let collateral_for_min_borrow_unit = liq_fee * lowest_borrow_price;
 
-        collateral_for_min_borrow_unit > largest_deposit_value.as_u64(0)
+        collateral_for_min_borrow_unit > self.largest_deposit_value
     }
 }
 
@@ -328,4 +555,11 @@ impl UserPosition {
     pub fn total_borrows(&self, cypher_token: &CypherToken) -> Number {
         self.base_borrows() * cypher_token.borrow_index()
     }
+
+    /// checks if the position has no deposits, no borrows and no open orders account
+    pub fn is_empty(&self) -> bool {
+        self.base_deposits() == Number::ZERO
+            && self.base_borrows() == Number::ZERO
+            && !self.oo_info.is_account_open
+    }
 }
@@ -0,0 +1,233 @@
+//! batch scanner for liquidatable cypher users, meant to run over the accounts returned by `getProgramAccounts`.
+use {
+    crate::{CypherGroup, CypherUser, MarginError, MarginValues},
+    anchor_lang::{prelude::*, Discriminator},
+    bytemuck::try_from_bytes,
+    jet_proto_math::Number,
+};
+
+/// an error decoding or valuing a scanned account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanError {
+    /// the account data is too short to hold a cypher user
+    InvalidLength,
+    /// the account data does not start with the cypher user discriminator
+    InvalidDiscriminator,
+    /// the cypher user belongs to another group
+    WrongGroup,
+    Margin(MarginError),
+}
+
+/// a cypher user below the group's maintenance ratio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidationCandidate {
+    pub cypher_user: Pubkey,
+    pub values: MarginValues,
+    pub c_ratio: Number,
+    /// the liability value to repay to bring the user back to the partial ratio, in native quote units
+    pub repay_value: Number,
+    /// the liquidator's bonus for repaying `repay_value`, in native quote units
+    ///
+    /// this is an estimate over the whole account, a single liquidation is also bounded by the seized asset and the repaid liability
+    pub expected_profit: Number,
+}
+
+/// the outcome of a scan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanResult {
+    /// the users below the maintenance ratio which are not bankrupt, highest expected profit first
+    pub liquidatable: Vec<LiquidationCandidate>,
+    /// the bankrupt users, lowest c-ratio first, these can not be liquidated and need their losses socialized
+    pub bankrupt: Vec<LiquidationCandidate>,
+    /// the accounts which could not be decoded or valued
+    pub errors: Vec<(Pubkey, ScanError)>,
+    /// the number of accounts scanned
+    pub scanned: usize,
+}
+
+/// decodes a cypher user from raw account data, without panicking on malformed data
+pub fn decode_cypher_user(data: &[u8]) -> std::result::Result<&CypherUser, ScanError> {
+    let end = std::mem::size_of::<CypherUser>() + 8;
+    if data.len() < end {
+        return Err(ScanError::InvalidLength);
+    }
+    if data[..8] != CypherUser::discriminator() {
+        return Err(ScanError::InvalidDiscriminator);
+    }
+    try_from_bytes::<CypherUser>(&data[8..end]).map_err(|_| ScanError::InvalidLength)
+}
+
+/// gets the liability value to repay, and the liquidator's bonus for it, to bring a user back to the partial ratio
+///
+/// repaying `r` seizes `r * fee` of assets, where `fee` includes the liquidator's bonus and the insurance fee,
+/// so the partial ratio `p` is reached for `r = (p * liabs - assets) / (p - fee)`
+pub fn get_repay_value(group: &CypherGroup, values: &MarginValues) -> (Number, Number) {
+    let liq_fee = group.liq_liqor_fee() + group.liq_insurance_fee();
+    let partial_ratio = group.margin_partial_ratio();
+    let max_repay_value = Number::min(values.liabilities_value, values.assets_value / liq_fee);
+    let target_liabs_value = partial_ratio * values.liabilities_value;
+    let repay_value = if partial_ratio > liq_fee && target_liabs_value > values.assets_value {
+        Number::min(
+            (target_liabs_value - values.assets_value) / (partial_ratio - liq_fee),
+            max_repay_value,
+        )
+    } else {
+        max_repay_value
+    };
+    let expected_profit = repay_value * (group.liq_liqor_fee() - Number::ONE);
+    (repay_value, expected_profit)
+}
+
+/// scans the given accounts for cypher users of the group which are below its maintenance ratio
+///
+/// every user is valued in a single pass, see [`CypherUser::get_margin_values`]
+pub fn scan_users<'a>(
+    group: &CypherGroup,
+    accounts: impl IntoIterator<Item = (Pubkey, &'a [u8])>,
+) -> ScanResult {
+    let margin_maint_ratio = group.margin_maint_ratio();
+    let mut result = ScanResult::default();
+    for (cypher_user, data) in accounts {
+        result.scanned += 1;
+        let user = match decode_cypher_user(data) {
+            Ok(user) => user,
+            Err(err) => {
+                result.errors.push((cypher_user, err));
+                continue;
+            }
+        };
+        if user.cypher_group != group.self_address {
+            result.errors.push((cypher_user, ScanError::WrongGroup));
+            continue;
+        }
+        let values = match user.get_margin_values(group) {
+            Ok(values) => values,
+            Err(err) => {
+                result.errors.push((cypher_user, ScanError::Margin(err)));
+                continue;
+            }
+        };
+        let c_ratio = values.c_ratio();
+        if c_ratio >= margin_maint_ratio {
+            continue;
+        }
+        let (repay_value, expected_profit) = get_repay_value(group, &values);
+        let candidate = LiquidationCandidate {
+            cypher_user,
+            values,
+            c_ratio,
+            repay_value,
+            expected_profit,
+        };
+        if values.is_bankrupt(group) {
+            result.bankrupt.push(candidate);
+        } else {
+            result.liquidatable.push(candidate);
+        }
+    }
+
+    result.liquidatable.sort_by(|a, b| {
+        b.expected_profit
+            .cmp(&a.expected_profit)
+            .then_with(|| a.c_ratio.cmp(&b.c_ratio))
+    });
+    result.bankrupt.sort_by(|a, b| a.c_ratio.cmp(&b.c_ratio));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{constants::QUOTE_TOKEN_IDX, test_utils::*},
+    };
+
+    fn group() -> Box<CypherGroup> {
+        CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build()
+    }
+
+    fn user_data(group: &CypherGroup, quote_deposits: u64, borrows: u64) -> Vec<u8> {
+        let user = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(QUOTE_TOKEN_IDX, quote_deposits)
+            .borrow(0, borrows)
+            .build();
+        to_account_data(user.as_ref())
+    }
+
+    #[test]
+    fn rejects_malformed_accounts() {
+        let group = group();
+        let data = user_data(&group, 100, 0);
+        assert_eq!(
+            decode_cypher_user(&data[..data.len() - 1]).err(),
+            Some(ScanError::InvalidLength)
+        );
+        let mut wrong_discriminator = data.clone();
+        wrong_discriminator[0] ^= 1;
+        assert_eq!(
+            decode_cypher_user(&wrong_discriminator).err(),
+            Some(ScanError::InvalidDiscriminator)
+        );
+        assert!(decode_cypher_user(&data).is_ok());
+    }
+
+    #[test]
+    fn scans_liquidatable_and_bankrupt_users() {
+        let group = group();
+        let other_group = CypherGroupBuilder::new().build();
+        let delisted = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(QUOTE_TOKEN_IDX, 100)
+            .borrow(1, 1)
+            .build();
+        let accounts: Vec<(Pubkey, Vec<u8>)> = vec![
+            // 125%, healthy
+            user_data(&group, 100_000_000, 2_000_000),
+            // 104%, the larger account is the more profitable one
+            user_data(&group, 50_000_000, 1_200_000),
+            user_data(&group, 100_000_000, 2_400_000),
+            // no deposit covers the liquidation of a single unit of the borrow
+            user_data(&group, 10, 1_000),
+            user_data(&group, 0, 1_000),
+            user_data(&other_group, 100, 0),
+            to_account_data(delisted.as_ref()),
+            vec![0; 8],
+        ]
+        .into_iter()
+        .map(|data| (Pubkey::new_unique(), data))
+        .collect();
+        let keys: Vec<Pubkey> = accounts.iter().map(|(pk, _)| *pk).collect();
+
+        let result = scan_users(
+            &group,
+            accounts.iter().map(|(pk, data)| (*pk, data.as_slice())),
+        );
+        assert_eq!(result.scanned, 8);
+
+        let liquidatable: Vec<Pubkey> = result.liquidatable.iter().map(|c| c.cypher_user).collect();
+        assert_eq!(liquidatable, vec![keys[2], keys[1]]);
+        let best = result.liquidatable[0];
+        assert!(best.c_ratio < group.margin_maint_ratio());
+        assert!(best.expected_profit > result.liquidatable[1].expected_profit);
+        // the repay value is bounded by the assets the liquidator can seize
+        let liq_fee = group.liq_liqor_fee() + group.liq_insurance_fee();
+        assert_eq!(best.repay_value, Number::from(100_000_000_u64) / liq_fee);
+        assert_eq!(
+            best.expected_profit,
+            best.repay_value * (group.liq_liqor_fee() - Number::ONE)
+        );
+
+        let bankrupt: Vec<Pubkey> = result.bankrupt.iter().map(|c| c.cypher_user).collect();
+        assert_eq!(bankrupt, vec![keys[4], keys[3]]);
+
+        assert_eq!(
+            result.errors,
+            vec![
+                (keys[5], ScanError::WrongGroup),
+                (keys[6], ScanError::Margin(MarginError::MarketNotListed(1))),
+                (keys[7], ScanError::InvalidLength),
+            ]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! margin helpers for on-chain programs which read the cypher accounts through `AccountLoader`s,
+//! the accounts are borrowed in place instead of being copied onto the stack or the heap.
+use {
+    crate::{CypherGroup, CypherUser, MarginError},
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+};
+
+/// a missing quote token is an `UninitializedAccount` of the group, and a position in a market which is not listed
+/// is `InvalidAccountData` of the user
+impl From<MarginError> for anchor_lang::error::Error {
+    fn from(err: MarginError) -> Self {
+        msg!("cypher margin error: {:?}", err);
+        match err {
+            MarginError::QuoteTokenNotListed => ProgramError::UninitializedAccount.into(),
+            MarginError::MarketNotListed(_) => ProgramError::InvalidAccountData.into(),
+        }
+    }
+}
+
+/// gets the group's margin initialization ratio
+pub fn margin_init_ratio(group: &AccountLoader<CypherGroup>) -> Result<Number> {
+    Ok(group.load()?.margin_init_ratio())
+}
+
+/// gets the group's margin maintenance ratio
+pub fn margin_maint_ratio(group: &AccountLoader<CypherGroup>) -> Result<Number> {
+    Ok(group.load()?.margin_maint_ratio())
+}
+
+/// gets the group's partial margin ratio
+pub fn margin_partial_ratio(group: &AccountLoader<CypherGroup>) -> Result<Number> {
+    Ok(group.load()?.margin_partial_ratio())
+}
+
+/// gets the users's assets value
+pub fn get_assets_value(
+    user: &AccountLoader<CypherUser>,
+    group: &AccountLoader<CypherGroup>,
+) -> Result<Number> {
+    Ok(user.load()?.get_assets_value(&group.load()?)?)
+}
+
+/// gets the users's liabilities value
+pub fn get_liabilities_value(
+    user: &AccountLoader<CypherUser>,
+    group: &AccountLoader<CypherGroup>,
+) -> Result<Number> {
+    Ok(user.load()?.get_liabilities_value(&group.load()?)?)
+}
+
+/// gets the user's margin c-ratio
+pub fn get_margin_c_ratio(
+    user: &AccountLoader<CypherUser>,
+    group: &AccountLoader<CypherGroup>,
+) -> Result<Number> {
+    Ok(user.load()?.get_margin_c_ratio(&group.load()?)?)
+}
+
+/// gets the user's margin c-ratio components, see [`CypherUser::get_margin_c_ratio_components`]
+pub fn get_margin_c_ratio_components(
+    user: &AccountLoader<CypherUser>,
+    group: &AccountLoader<CypherGroup>,
+) -> Result<(Number, Number, Number)> {
+    Ok(user.load()?.get_margin_c_ratio_components(&group.load()?)?)
+}
+
+/// checks if the user is bankrupt
+pub fn is_bankrupt(
+    user: &AccountLoader<CypherUser>,
+    group: &AccountLoader<CypherGroup>,
+) -> Result<bool> {
+    Ok(user.load()?.is_bankrupt(&group.load()?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{constants::QUOTE_TOKEN_IDX, test_utils::*},
+    };
+
+    #[test]
+    fn values_accounts_through_account_loaders() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let user = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+            .borrow(0, 2_000_000)
+            .build();
+        let program_id = crate::id();
+        let (group_key, user_key) = (group.self_address, Pubkey::new_unique());
+        let (mut group_lamports, mut user_lamports) = (0, 0);
+        let mut group_data = to_account_data(&*group);
+        let mut user_data = to_account_data(&*user);
+        let group_info = AccountInfo::new(
+            &group_key,
+            false,
+            false,
+            &mut group_lamports,
+            &mut group_data,
+            &program_id,
+            false,
+            0,
+        );
+        let user_info = AccountInfo::new(
+            &user_key,
+            false,
+            false,
+            &mut user_lamports,
+            &mut user_data,
+            &program_id,
+            false,
+            0,
+        );
+        let group_loader = AccountLoader::<CypherGroup>::try_from(&group_info).unwrap();
+        let user_loader = AccountLoader::<CypherUser>::try_from(&user_info).unwrap();
+
+        let values = user.get_margin_values(&group).unwrap();
+        assert_eq!(
+            get_assets_value(&user_loader, &group_loader).unwrap(),
+            values.assets_value
+        );
+        assert_eq!(
+            get_liabilities_value(&user_loader, &group_loader).unwrap(),
+            values.liabilities_value
+        );
+        assert_eq!(
+            get_margin_c_ratio(&user_loader, &group_loader).unwrap(),
+            values.c_ratio()
+        );
+        assert_eq!(
+            get_margin_c_ratio_components(&user_loader, &group_loader).unwrap(),
+            (
+                values.c_ratio(),
+                values.assets_value,
+                values.liabilities_value
+            )
+        );
+        assert_eq!(
+            is_bankrupt(&user_loader, &group_loader).unwrap(),
+            user.is_bankrupt(&group).unwrap()
+        );
+        assert_eq!(
+            margin_init_ratio(&group_loader).unwrap(),
+            group.margin_init_ratio()
+        );
+    }
+
+    #[test]
+    fn maps_margin_errors_to_program_errors() {
+        assert_eq!(
+            anchor_lang::error::Error::from(MarginError::QuoteTokenNotListed),
+            ProgramError::UninitializedAccount.into()
+        );
+        assert_eq!(
+            anchor_lang::error::Error::from(MarginError::MarketNotListed(1)),
+            ProgramError::InvalidAccountData.into()
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! CPI wrappers for the margin instructions which take the accounts to append to the instruction explicitly.
+//!
+//! the idl declares no remaining accounts for `deposit_collateral`, `withdraw_collateral`, `liquidate_collateral` and
+//! `settle_position`: the users' open orders balances are tracked in [`crate::OpenOrdersInfo`] "without passing
+//! OpenOrders accounts for every single markets", and the margin checks use the oracle prices cached in the group by
+//! `cache_oracle_price`. the program's source is not bundled with this crate, so whether any of these instructions
+//! reads extra accounts, such as the liqee's or the user's open orders or price data, and in which order, is unknown.
+//! the wrappers append the given `remaining_accounts` as is, after the accounts of the idl and in the given order, an
+//! empty slice matches the idl.
+//!
+//! the idl documents `InvalidLiquidation` (6021) as "tried to liquidate a healthy position", it does not relate it to
+//! missing accounts.
+use {
+    crate::{
+        client::{
+            deposit_collateral_ix, liquidate_collateral_ix, settle_position_ix,
+            withdraw_collateral_ix,
+        },
+        cpi::accounts::{
+            DepositCollateral as DepositCollateralCpi,
+            LiquidateCollateral as LiquidateCollateralCpi, SettlePosition as SettlePositionCpi,
+            WithdrawCollateral as WithdrawCollateralCpi,
+        },
+        signer::UserSigner,
+    },
+    anchor_lang::{
+        prelude::*,
+        solana_program::{instruction::Instruction, program::invoke_signed},
+    },
+};
+
+/// appends the remaining accounts to the instruction's accounts, after the accounts of the idl and in the given order
+fn append_remaining_accounts(ix: &mut Instruction, remaining_accounts: &[AccountInfo]) {
+    ix.accounts
+        .extend(remaining_accounts.iter().map(|a| AccountMeta {
+            pubkey: *a.key,
+            is_signer: a.is_signer,
+            is_writable: a.is_writable,
+        }));
+}
+
+/// invokes the instruction with the context's accounts followed by the given remaining accounts
+///
+/// the context's own `remaining_accounts` are not forwarded, so that no account is appended implicitly
+fn invoke_with_remaining_accounts<'info, T: ToAccountInfos<'info> + ToAccountMetas>(
+    mut ix: Instruction,
+    ctx: &CpiContext<'_, '_, '_, 'info, T>,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<()> {
+    append_remaining_accounts(&mut ix, remaining_accounts);
+    let mut account_infos = ctx.accounts.to_account_infos();
+    account_infos.extend_from_slice(remaining_accounts);
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds)?;
+    Ok(())
+}
+
+pub fn deposit_collateral<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, DepositCollateralCpi<'info>>,
+    remaining_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    let ix = deposit_collateral_ix(
+        ctx.accounts.cypher_group.key,
+        ctx.accounts.cypher_user.key,
+        ctx.accounts.cypher_pc_vault.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
+        ctx.accounts.deposit_from.key,
+        amount,
+    );
+    invoke_with_remaining_accounts(ix, &ctx, remaining_accounts)
+}
+
+pub fn withdraw_collateral<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, WithdrawCollateralCpi<'info>>,
+    remaining_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    let ix = withdraw_collateral_ix(
+        ctx.accounts.cypher_group.key,
+        ctx.accounts.cypher_user.key,
+        ctx.accounts.cypher_pc_vault.key,
+        ctx.accounts.vault_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
+        ctx.accounts.withdraw_to.key,
+        amount,
+    )?;
+    invoke_with_remaining_accounts(ix, &ctx, remaining_accounts)
+}
+
+pub fn liquidate_collateral<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, LiquidateCollateralCpi<'info>>,
+    remaining_accounts: &[AccountInfo<'info>],
+    asset_mint: Pubkey,
+    liab_mint: Pubkey,
+) -> Result<()> {
+    let ix = liquidate_collateral_ix(
+        ctx.accounts.cypher_group.key,
+        ctx.accounts.cypher_user.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
+        ctx.accounts.liqee_cypher_user.key,
+        &asset_mint,
+        &liab_mint,
+    );
+    invoke_with_remaining_accounts(ix, &ctx, remaining_accounts)
+}
+
+pub fn settle_position<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, SettlePositionCpi<'info>>,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<()> {
+    let ix = settle_position_ix(
+        ctx.accounts.cypher_group.key,
+        ctx.accounts.cypher_user.key,
+        ctx.accounts.c_asset_mint.key,
+    );
+    invoke_with_remaining_accounts(ix, &ctx, remaining_accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_remaining_accounts_after_the_idl_accounts_in_order() {
+        let (group, user, signer, liqee) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut ix = liquidate_collateral_ix(
+            &group,
+            &user,
+            &UserSigner::Owner(signer),
+            &liqee,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        let idl_accounts = ix.accounts.clone();
+
+        let (open_orders, price_data, owner) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (mut open_orders_lamports, mut price_data_lamports) = (0, 0);
+        let (mut open_orders_data, mut price_data_data) = (vec![], vec![]);
+        let remaining_accounts = [
+            AccountInfo::new(
+                &open_orders,
+                false,
+                true,
+                &mut open_orders_lamports,
+                &mut open_orders_data,
+                &owner,
+                false,
+                0,
+            ),
+            AccountInfo::new(
+                &price_data,
+                true,
+                false,
+                &mut price_data_lamports,
+                &mut price_data_data,
+                &owner,
+                false,
+                0,
+            ),
+        ];
+        append_remaining_accounts(&mut ix, &remaining_accounts);
+
+        assert_eq!(ix.accounts.len(), idl_accounts.len() + 2);
+        assert_eq!(&ix.accounts[..idl_accounts.len()], &idl_accounts[..]);
+        assert_eq!(
+            &ix.accounts[idl_accounts.len()..],
+            &[
+                AccountMeta::new(open_orders, false),
+                AccountMeta::new_readonly(price_data, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_remaining_accounts_match_the_idl() {
+        let mut ix = settle_position_ix(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        let idl_accounts = ix.accounts.clone();
+        append_remaining_accounts(&mut ix, &[]);
+        assert_eq!(ix.accounts, idl_accounts);
+    }
+}
@@ -0,0 +1,203 @@
+//! lookup of the resting orders of a single open orders account in a decoded bids or asks `Slab`.
+use {
+    crate::{
+        client::ToPubkey,
+        orders::{OrderContext, OrderError},
+        serum_slab::Slab,
+    },
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+    serum_dex::matching::Side,
+};
+
+/// a resting order of an open orders account and its position in the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnOrder {
+    pub side: Side,
+    pub order_id: u128,
+    pub price_lots: u64,
+    pub quantity_lots: u64,
+    pub client_order_id: u64,
+    /// the order's slot in the open orders account
+    pub owner_slot: u8,
+    /// the number of orders ahead of this one at the same price
+    pub orders_ahead: usize,
+    /// the quantity ahead of this one at the same price, in lots
+    pub quantity_ahead_lots: u64,
+}
+
+impl OwnOrder {
+    /// gets the price in quote per coin
+    pub fn price(&self, ctx: &OrderContext) -> Result<Number, OrderError> {
+        ctx.lots_to_price(self.price_lots)
+    }
+
+    /// gets the size in coin
+    pub fn size(&self, ctx: &OrderContext) -> Result<Number, OrderError> {
+        ctx.lots_to_size(self.quantity_lots)
+    }
+
+    /// gets the size ahead of this order at the same price in coin
+    pub fn size_ahead(&self, ctx: &OrderContext) -> Result<Number, OrderError> {
+        ctx.lots_to_size(self.quantity_ahead_lots)
+    }
+
+    /// checks whether this order is first in line at its price
+    pub fn is_first_in_queue(&self) -> bool {
+        self.orders_ahead == 0
+    }
+}
+
+/// finds the orders of the given open orders account on one side of the book, best price first
+pub fn find_orders(book: &Slab, is_asks: bool, open_orders: &Pubkey) -> Vec<OwnOrder> {
+    let side = if is_asks { Side::Ask } else { Side::Bid };
+    let mut orders = Vec::new();
+    let mut level_price = 0;
+    let mut orders_ahead = 0;
+    let mut quantity_ahead_lots = 0;
+    for order in book.get_orders(is_asks) {
+        let price_lots = u64::from(order.price());
+        if price_lots != level_price {
+            level_price = price_lots;
+            orders_ahead = 0;
+            quantity_ahead_lots = 0;
+        }
+        if order.owner().to_pubkey() == *open_orders {
+            orders.push(OwnOrder {
+                side,
+                order_id: order.order_id(),
+                price_lots,
+                quantity_lots: order.quantity(),
+                client_order_id: order.client_order_id(),
+                owner_slot: order.owner_slot(),
+                orders_ahead,
+                quantity_ahead_lots,
+            });
+        }
+        orders_ahead += 1;
+        quantity_ahead_lots += order.quantity();
+    }
+    orders
+}
+
+/// finds the orders of the given open orders account on both sides of the book, bids first
+pub fn find_all_orders(bids: &Slab, asks: &Slab, open_orders: &Pubkey) -> Vec<OwnOrder> {
+    let mut orders = find_orders(bids, false, open_orders);
+    orders.extend(find_orders(asks, true, open_orders));
+    orders
+}
+
+/// finds the order with the given client order id, on either side of the book
+pub fn find_order_by_client_id(
+    bids: &Slab,
+    asks: &Slab,
+    open_orders: &Pubkey,
+    client_order_id: u64,
+) -> Option<OwnOrder> {
+    find_all_orders(bids, asks, open_orders)
+        .into_iter()
+        .find(|o| o.client_order_id == client_order_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*};
+
+    struct Fixture {
+        alice: Pubkey,
+        bob: Pubkey,
+        bids: Vec<u8>,
+        asks: Vec<u8>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+            let bids = SlabBuilder::bids()
+                .order(1, 100, 5, &bob)
+                .order(2, 100, 3, &alice)
+                .order(3, 100, 4, &alice)
+                .order(4, 99, 7, &bob)
+                .order(5, 99, 2, &alice)
+                .order(6, 98, 1, &alice)
+                .build();
+            let asks = SlabBuilder::asks()
+                .order(7, 101, 2, &alice)
+                .order(8, 102, 3, &bob)
+                .build();
+            Self {
+                alice,
+                bob,
+                bids,
+                asks,
+            }
+        }
+
+        fn bids(&self) -> &Slab {
+            Slab::from_bytes(&self.bids)
+        }
+
+        fn asks(&self) -> &Slab {
+            Slab::from_bytes(&self.asks)
+        }
+    }
+
+    #[test]
+    fn queue_position_resets_per_level() {
+        let f = Fixture::new();
+        let orders = find_orders(f.bids(), false, &f.alice);
+        let positions: Vec<(u64, u64, usize, u64)> = orders
+            .iter()
+            .map(|o| {
+                (
+                    o.client_order_id,
+                    o.price_lots,
+                    o.orders_ahead,
+                    o.quantity_ahead_lots,
+                )
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![(2, 100, 1, 5), (3, 100, 2, 8), (5, 99, 1, 7), (6, 98, 0, 0)]
+        );
+        assert!(orders.iter().all(|o| o.side == Side::Bid));
+        assert!(orders[3].is_first_in_queue());
+        assert_eq!(orders[0].order_id, order_id(100, 2, false));
+        assert_eq!(orders[0].quantity_lots, 3);
+
+        let orders = find_orders(f.bids(), false, &f.bob);
+        assert_eq!(orders.len(), 2);
+        assert!(orders.iter().all(|o| o.is_first_in_queue()));
+        assert!(find_orders(f.bids(), false, &Pubkey::new_unique()).is_empty());
+    }
+
+    #[test]
+    fn finds_orders_on_both_sides() {
+        let f = Fixture::new();
+        let orders = find_all_orders(f.bids(), f.asks(), &f.alice);
+        let client_order_ids: Vec<u64> = orders.iter().map(|o| o.client_order_id).collect();
+        assert_eq!(client_order_ids, vec![2, 3, 5, 6, 7]);
+        assert_eq!(orders[4].side, Side::Ask);
+        assert!(orders[4].is_first_in_queue());
+    }
+
+    #[test]
+    fn finds_order_by_client_id() {
+        let f = Fixture::new();
+        let order = find_order_by_client_id(f.bids(), f.asks(), &f.alice, 7).unwrap();
+        assert_eq!(order.side, Side::Ask);
+        assert_eq!(order.price_lots, 101);
+        let order = find_order_by_client_id(f.bids(), f.asks(), &f.alice, 5).unwrap();
+        assert_eq!((order.side, order.price_lots), (Side::Bid, 99));
+        // the order belongs to another open orders account
+        assert_eq!(
+            find_order_by_client_id(f.bids(), f.asks(), &f.alice, 4),
+            None
+        );
+        assert_eq!(
+            find_order_by_client_id(f.bids(), f.asks(), &f.alice, 9),
+            None
+        );
+    }
+}
@@ -0,0 +1,426 @@
+use {
+    crate::{
+        client::ToPubkey,
+        constants::*,
+        utils::{checked_as_u64, checked_as_u64_ceil},
+        CypherGroup,
+    },
+    jet_proto_math::Number,
+    serum_dex::{
+        instruction::{NewOrderInstructionV3, SelfTradeBehavior},
+        matching::{OrderType, Side},
+        state::MarketState,
+    },
+    std::num::NonZeroU64,
+};
+
+/// the number of decimal places kept when converting an `f64` into a `Number`
+const F64_PRECISION: u32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderError {
+    /// the price is not finite, negative or rounds down to zero lots
+    InvalidPrice,
+    /// the size is not finite, negative or rounds down to zero lots
+    InvalidSize,
+    /// the token or market could not be found in the cypher group
+    UnableToFindTokenOrMarket,
+    /// the slippage is not finite, negative or above 100% for an ask
+    InvalidSlippage,
+    /// the token's decimals are too large for its unit to fit in a `u64`
+    InvalidDecimals,
+    /// the native quote amount of the order does not fit in a `u64`
+    AmountOverflow,
+    /// the dex market is not the one listed in the cypher group at the market index
+    MarketMismatch,
+}
+
+/// gets the native amount of one unit of a token with the given decimals
+pub(crate) fn get_unit(decimals: u8) -> Result<Number, OrderError> {
+    10_u64
+        .checked_pow(decimals as u32)
+        .map(Number::from)
+        .ok_or(OrderError::InvalidDecimals)
+}
+
+/// a human-unit amount which can be converted into a `Number`
+pub trait IntoNumber {
+    fn into_number(self) -> Option<Number>;
+}
+
+impl IntoNumber for Number {
+    fn into_number(self) -> Option<Number> {
+        Some(self)
+    }
+}
+
+impl IntoNumber for f64 {
+    fn into_number(self) -> Option<Number> {
+        if !self.is_finite() || self < 0.0 {
+            return None;
+        }
+        let scaled = (self * 10_f64.powi(F64_PRECISION as i32)).round();
+        if scaled > u64::MAX as f64 {
+            return None;
+        }
+        Some(Number::from_decimal(scaled as u64, -(F64_PRECISION as i32)))
+    }
+}
+
+/// the market parameters needed to convert human units into serum lots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderContext {
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub coin_decimals: u8,
+    pub pc_decimals: u8,
+    pub taker_fee_bps: u16,
+}
+
+impl OrderContext {
+    /// creates the context for the market at the given index from the cypher group and the decoded dex market
+    ///
+    /// the dex market must be the one listed at the market index, otherwise this fails with
+    /// [`OrderError::MarketMismatch`]
+    pub fn new(
+        group: &CypherGroup,
+        market_idx: usize,
+        dex_market: &MarketState,
+    ) -> Result<Self, OrderError> {
+        if market_idx >= MARKETS_MAX_CNT {
+            return Err(OrderError::UnableToFindTokenOrMarket);
+        }
+        let market = group
+            .get_cypher_market(market_idx)
+            .ok_or(OrderError::UnableToFindTokenOrMarket)?;
+        if market.dex_market != dex_market.own_address.to_pubkey() {
+            return Err(OrderError::MarketMismatch);
+        }
+        let coin_token = group
+            .get_cypher_token(market_idx)
+            .ok_or(OrderError::UnableToFindTokenOrMarket)?;
+        let pc_token = group
+            .get_cypher_token(QUOTE_TOKEN_IDX)
+            .ok_or(OrderError::UnableToFindTokenOrMarket)?;
+        get_unit(coin_token.decimals())?;
+        get_unit(pc_token.decimals())?;
+        Ok(Self {
+            coin_lot_size: dex_market.coin_lot_size,
+            pc_lot_size: dex_market.pc_lot_size,
+            coin_decimals: coin_token.decimals(),
+            pc_decimals: pc_token.decimals(),
+            taker_fee_bps: group.config.taker_fee_bps,
+        })
+    }
+
+    /// gets the native amount of one coin
+    pub fn coin_unit(&self) -> Result<Number, OrderError> {
+        get_unit(self.coin_decimals)
+    }
+
+    /// gets the native amount of one quote
+    pub fn pc_unit(&self) -> Result<Number, OrderError> {
+        get_unit(self.pc_decimals)
+    }
+
+    /// gets the group's taker fee
+    pub fn taker_fee(&self) -> Number {
+        Number::from_bps(self.taker_fee_bps)
+    }
+
+    /// converts a price in quote per coin into a price in pc lots per coin lot
+    pub fn price_to_lots(&self, price: Number) -> Result<Number, OrderError> {
+        Ok(price * self.pc_unit()? * self.coin_lot_size / (self.coin_unit()? * self.pc_lot_size))
+    }
+
+    /// converts a price in pc lots per coin lot into a price in quote per coin
+    pub fn lots_to_price(&self, price_lots: u64) -> Result<Number, OrderError> {
+        Ok(
+            Number::from(price_lots) * self.coin_unit()? * self.pc_lot_size
+                / (self.pc_unit()? * self.coin_lot_size),
+        )
+    }
+
+    /// converts a size in coin into a size in coin lots
+    pub fn size_to_lots(&self, size: Number) -> Result<Number, OrderError> {
+        Ok(size * self.coin_unit()? / self.coin_lot_size)
+    }
+
+    /// converts a size in coin lots into a size in coin
+    pub fn lots_to_size(&self, size_lots: u64) -> Result<Number, OrderError> {
+        Ok(Number::from(size_lots) * self.coin_lot_size / self.coin_unit()?)
+    }
+}
+
+/// builds a `NewOrderInstructionV3` from a price and size in human units
+#[derive(Debug, Clone, Copy)]
+pub struct OrderBuilder {
+    side: Side,
+    order_type: OrderType,
+    self_trade_behavior: SelfTradeBehavior,
+    client_order_id: u64,
+    limit: u16,
+}
+
+impl OrderBuilder {
+    /// creates a limit order builder for the given side
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            order_type: OrderType::Limit,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            client_order_id: 0,
+            limit: u16::MAX,
+        }
+    }
+
+    /// creates a limit order builder for a bid
+    pub fn bid() -> Self {
+        Self::new(Side::Bid)
+    }
+
+    /// creates a limit order builder for an ask
+    pub fn ask() -> Self {
+        Self::new(Side::Ask)
+    }
+
+    pub fn order_type(mut self, order_type: OrderType) -> Self {
+        self.order_type = order_type;
+        self
+    }
+
+    pub fn limit_order(self) -> Self {
+        self.order_type(OrderType::Limit)
+    }
+
+    pub fn post_only(self) -> Self {
+        self.order_type(OrderType::PostOnly)
+    }
+
+    pub fn immediate_or_cancel(self) -> Self {
+        self.order_type(OrderType::ImmediateOrCancel)
+    }
+
+    pub fn self_trade_behavior(mut self, self_trade_behavior: SelfTradeBehavior) -> Self {
+        self.self_trade_behavior = self_trade_behavior;
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: u64) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    /// sets the maximum number of orders the matching engine may iterate over
+    pub fn limit(mut self, limit: u16) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// builds the serum instruction data for the given price and size in human units
+    ///
+    /// bid prices are rounded down and ask prices are rounded up so that the order is never worse than requested,
+    /// the size is always rounded down and the quote amount for bids includes the group's taker fee unless the order is post-only
+    pub fn build(
+        &self,
+        ctx: &OrderContext,
+        price: impl IntoNumber,
+        size: impl IntoNumber,
+    ) -> Result<NewOrderInstructionV3, OrderError> {
+        let price = price.into_number().ok_or(OrderError::InvalidPrice)?;
+        let size = size.into_number().ok_or(OrderError::InvalidSize)?;
+
+        let price_lots = ctx.price_to_lots(price)?;
+        let limit_price = match self.side {
+            Side::Bid => checked_as_u64(price_lots),
+            Side::Ask => checked_as_u64_ceil(price_lots),
+        };
+        let limit_price = limit_price
+            .and_then(NonZeroU64::new)
+            .ok_or(OrderError::InvalidPrice)?;
+        let max_coin_qty = checked_as_u64(ctx.size_to_lots(size)?)
+            .and_then(NonZeroU64::new)
+            .ok_or(OrderError::InvalidSize)?;
+
+        let max_native_pc_qty_including_fees = match self.side {
+            Side::Bid => {
+                let native_pc_qty =
+                    Number::from(limit_price.get()) * max_coin_qty.get() * ctx.pc_lot_size;
+                let native_pc_qty = match self.order_type {
+                    OrderType::PostOnly => native_pc_qty,
+                    _ => native_pc_qty * (Number::ONE + ctx.taker_fee()),
+                };
+                let native_pc_qty =
+                    checked_as_u64_ceil(native_pc_qty).ok_or(OrderError::AmountOverflow)?;
+                NonZeroU64::new(native_pc_qty).ok_or(OrderError::InvalidSize)?
+            }
+            Side::Ask => NonZeroU64::new(u64::MAX).unwrap(),
+        };
+
+        Ok(NewOrderInstructionV3 {
+            side: self.side,
+            limit_price,
+            max_coin_qty,
+            max_native_pc_qty_including_fees,
+            self_trade_behavior: self.self_trade_behavior,
+            order_type: self.order_type,
+            client_order_id: self.client_order_id,
+            limit: self.limit,
+            max_ts: i64::MAX,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::CypherGroupBuilder, anchor_lang::prelude::Pubkey};
+
+    fn dex_market(address: Pubkey) -> MarketState {
+        let mut dex_market: MarketState = unsafe { std::mem::zeroed() };
+        dex_market.own_address = bytemuck::cast(address.to_bytes());
+        dex_market.coin_lot_size = 100_000;
+        dex_market.pc_lot_size = 10;
+        dex_market
+    }
+
+    /// one coin lot is 0.1 coin and a price of 10_000 pc lots per coin lot is 1 quote per coin
+    fn ctx() -> OrderContext {
+        OrderContext {
+            coin_lot_size: 100_000,
+            pc_lot_size: 10,
+            coin_decimals: 6,
+            pc_decimals: 6,
+            taker_fee_bps: 10,
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_market() {
+        let address = Pubkey::new_unique();
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .dex_market(0, address)
+            .build();
+        assert_eq!(
+            OrderContext::new(&group, 0, &dex_market(address)),
+            Ok(OrderContext {
+                coin_lot_size: 100_000,
+                pc_lot_size: 10,
+                coin_decimals: 6,
+                pc_decimals: group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap().decimals(),
+                taker_fee_bps: group.config.taker_fee_bps,
+            })
+        );
+        assert_eq!(
+            OrderContext::new(&group, QUOTE_TOKEN_IDX, &dex_market(address)),
+            Err(OrderError::UnableToFindTokenOrMarket)
+        );
+        assert_eq!(
+            OrderContext::new(&group, usize::MAX, &dex_market(address)),
+            Err(OrderError::UnableToFindTokenOrMarket)
+        );
+        assert_eq!(
+            OrderContext::new(&group, 0, &dex_market(Pubkey::new_unique())),
+            Err(OrderError::MarketMismatch)
+        );
+    }
+
+    #[test]
+    fn rounds_prices_and_sizes_in_favor_of_the_user() {
+        let bid = OrderBuilder::bid().build(&ctx(), 1.23456, 1.05).unwrap();
+        assert_eq!(bid.limit_price.get(), 12_345);
+        assert_eq!(bid.max_coin_qty.get(), 10);
+
+        let ask = OrderBuilder::ask().build(&ctx(), 1.23456, 1.05).unwrap();
+        assert_eq!(ask.limit_price.get(), 12_346);
+        assert_eq!(ask.max_coin_qty.get(), 10);
+    }
+
+    #[test]
+    fn bid_quote_amount_includes_taker_fee_unless_post_only() {
+        // 12_345 pc lots * 10 coin lots * 10 native pc per lot
+        let bid = OrderBuilder::bid().build(&ctx(), 1.23456, 1.05).unwrap();
+        assert_eq!(bid.max_native_pc_qty_including_fees.get(), 1_235_735);
+
+        let post_only = OrderBuilder::bid()
+            .post_only()
+            .build(&ctx(), 1.23456, 1.05)
+            .unwrap();
+        assert_eq!(post_only.max_native_pc_qty_including_fees.get(), 1_234_500);
+        assert_eq!(post_only.order_type, OrderType::PostOnly);
+
+        let ask = OrderBuilder::ask().build(&ctx(), 1.23456, 1.05).unwrap();
+        assert_eq!(ask.max_native_pc_qty_including_fees.get(), u64::MAX);
+    }
+
+    #[test]
+    fn rejects_amounts_overflowing_u64() {
+        let ctx = ctx();
+        assert_eq!(
+            OrderBuilder::bid()
+                .build(&ctx, Number::from(u64::MAX), 1.0)
+                .err(),
+            Some(OrderError::InvalidPrice)
+        );
+        assert_eq!(
+            OrderBuilder::ask()
+                .build(&ctx, Number::from(u64::MAX), 1.0)
+                .err(),
+            Some(OrderError::InvalidPrice)
+        );
+        assert_eq!(
+            OrderBuilder::bid()
+                .build(&ctx, 1.0, Number::from(u64::MAX))
+                .err(),
+            Some(OrderError::InvalidSize)
+        );
+        assert_eq!(
+            OrderBuilder::bid()
+                .build(
+                    &ctx,
+                    Number::from(1_000_000_000_000_u64),
+                    Number::from(1_000_000_000_u64)
+                )
+                .err(),
+            Some(OrderError::AmountOverflow)
+        );
+        assert_eq!(
+            OrderBuilder::bid().build(&ctx, 0.00001, 1.0).err(),
+            Some(OrderError::InvalidPrice)
+        );
+        assert_eq!(
+            OrderBuilder::bid().build(&ctx, 1.0, 0.01).err(),
+            Some(OrderError::InvalidSize)
+        );
+    }
+
+    #[test]
+    fn rejects_decimals_overflowing_u64() {
+        let address = Pubkey::new_unique();
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 20, 40)
+            .dex_market(0, address)
+            .build();
+        assert_eq!(
+            OrderContext::new(&group, 0, &dex_market(address)),
+            Err(OrderError::InvalidDecimals)
+        );
+
+        let ctx = OrderContext {
+            coin_lot_size: 100_000,
+            pc_lot_size: 10,
+            coin_decimals: 19,
+            pc_decimals: 20,
+            taker_fee_bps: 10,
+        };
+        assert!(ctx.size_to_lots(Number::ONE).is_ok());
+        assert_eq!(
+            ctx.price_to_lots(Number::ONE),
+            Err(OrderError::InvalidDecimals)
+        );
+        assert_eq!(
+            OrderBuilder::bid().build(&ctx, 1.0, 1.0).err(),
+            Some(OrderError::InvalidDecimals)
+        );
+    }
+}
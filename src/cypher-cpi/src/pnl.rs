@@ -0,0 +1,496 @@
+//! position and pnl tracking of cypher users, rebuilt from the program's event logs.
+//!
+//! two accounting choices are made which a reader of the pnl should know about:
+//!
+//! - a cAsset deposit or withdrawal is booked as a trade at the market price when it was emitted, not as a transfer,
+//!   so withdrawing a cAsset realizes the pnl of the withdrawn quantity as if it was sold, and depositing one opens a
+//!   lot at the market price.
+//! - a liquidation sells the seized asset and buys back the repaid liability at the logged prices, so its proceeds
+//!   are part of the realized pnl, the value given up to the liquidator and the insurance fund is only tracked
+//!   separately in `liquidation_losses`, and is not subtracted from the realized pnl.
+use {
+    crate::{
+        fees::FeeSchedule, quote_mint, CypherGroup, CypherMarket, DepositOrWithdrawLog,
+        LiquidateMarginCollateralLog, OrderFillLog, SettlePositionLog,
+    },
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+    std::{
+        collections::{HashMap, VecDeque},
+        convert::TryFrom,
+    },
+};
+
+/// the cost basis method used to realize pnl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostBasisMethod {
+    Fifo,
+    AverageCost,
+}
+
+/// an open lot of the position, used for fifo accounting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lot {
+    /// the remaining coin quantity in native units
+    coin_qty: u64,
+    /// the quote cost of the remaining coin quantity in native units
+    pc_qty: u128,
+}
+
+/// the position and pnl of a cypher user in a single token
+///
+/// all amounts are in native units, pnl is in native quote units
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionLedger {
+    pub cypher_user: Pubkey,
+    pub c_asset_mint: Pubkey,
+    /// the signed position, positive for longs and negative for shorts
+    pub position: i128,
+    lots: VecDeque<Lot>,
+    /// the quote cost of the open position under average cost accounting
+    average_cost_basis: u128,
+    pub realized_pnl_fifo: i128,
+    pub realized_pnl_average_cost: i128,
+    pub fees_paid: u64,
+    /// the deposited amount, in native units of the ledger's token
+    pub deposits: u64,
+    /// the withdrawn amount, in native units of the ledger's token
+    pub withdrawals: u64,
+    /// the value lost by this user to liquidators and the insurance fund
+    pub liquidation_losses: u128,
+}
+
+impl PositionLedger {
+    pub fn new(cypher_user: Pubkey, c_asset_mint: Pubkey) -> Self {
+        Self {
+            cypher_user,
+            c_asset_mint,
+            position: 0,
+            lots: VecDeque::new(),
+            average_cost_basis: 0,
+            realized_pnl_fifo: 0,
+            realized_pnl_average_cost: 0,
+            fees_paid: 0,
+            deposits: 0,
+            withdrawals: 0,
+            liquidation_losses: 0,
+        }
+    }
+
+    /// gets the realized pnl for the given cost basis method
+    pub fn realized_pnl(&self, method: CostBasisMethod) -> i128 {
+        match method {
+            CostBasisMethod::Fifo => self.realized_pnl_fifo,
+            CostBasisMethod::AverageCost => self.realized_pnl_average_cost,
+        }
+    }
+
+    /// gets the quote cost of the open position for the given cost basis method
+    pub fn cost_basis(&self, method: CostBasisMethod) -> u128 {
+        match method {
+            CostBasisMethod::Fifo => self.lots.iter().map(|l| l.pc_qty).sum(),
+            CostBasisMethod::AverageCost => self.average_cost_basis,
+        }
+    }
+
+    /// gets the average entry price of the open position in native quote per native coin,
+    /// `None` if there is no open position or its cost or size do not fit in a u64
+    pub fn average_entry_price(&self) -> Option<Number> {
+        if self.position == 0 {
+            return None;
+        }
+        let cost = u64::try_from(self.average_cost_basis).ok()?;
+        let size = u64::try_from(self.position.unsigned_abs()).ok()?;
+        Some(Number::from(cost) / Number::from(size))
+    }
+
+    /// gets the unrealized pnl of the open position marked against the market price
+    pub fn unrealized_pnl(&self, market: &CypherMarket, method: CostBasisMethod) -> i128 {
+        let value = self
+            .position
+            .unsigned_abs()
+            .saturating_mul(market.market_price as u128);
+        let cost = self.cost_basis(method);
+        if self.position >= 0 {
+            signed_diff(value, cost)
+        } else {
+            signed_diff(cost, value)
+        }
+    }
+
+    /// applies a trade of `coin_qty` for `pc_qty`, buying if `is_bid` and selling otherwise
+    fn apply_trade(&mut self, is_bid: bool, coin_qty: u64, pc_qty: u128) {
+        if coin_qty == 0 {
+            return;
+        }
+        let is_long = self.position > 0;
+        let reduces = self.position != 0 && is_long != is_bid;
+        let (close_qty, close_pc) = if reduces {
+            let close_qty = u64::try_from(self.position.unsigned_abs())
+                .map_or(coin_qty, |position| position.min(coin_qty));
+            let close_pc = mul_div(pc_qty, close_qty as u128, coin_qty as u128);
+            (close_qty, close_pc)
+        } else {
+            (0, 0)
+        };
+
+        if close_qty > 0 {
+            let fifo_cost = self.pop_lots(close_qty);
+            let average_cost = mul_div(
+                self.average_cost_basis,
+                close_qty as u128,
+                self.position.unsigned_abs(),
+            );
+            self.average_cost_basis -= average_cost;
+
+            // closing a long sells the coin, closing a short buys it back
+            let sign = if is_long { 1 } else { -1 };
+            self.realized_pnl_fifo += sign * signed_diff(close_pc, fifo_cost);
+            self.realized_pnl_average_cost += sign * signed_diff(close_pc, average_cost);
+            self.position -= sign * close_qty as i128;
+        }
+
+        let open_qty = coin_qty - close_qty;
+        if open_qty > 0 {
+            let open_pc = pc_qty - close_pc;
+            self.lots.push_back(Lot {
+                coin_qty: open_qty,
+                pc_qty: open_pc,
+            });
+            self.average_cost_basis += open_pc;
+            self.position += if is_bid {
+                open_qty as i128
+            } else {
+                -(open_qty as i128)
+            };
+        }
+    }
+
+    /// removes `coin_qty` from the oldest lots, returning their quote cost
+    fn pop_lots(&mut self, mut coin_qty: u64) -> u128 {
+        let mut cost = 0;
+        while coin_qty > 0 {
+            let lot = match self.lots.front_mut() {
+                Some(lot) => lot,
+                None => break,
+            };
+            if lot.coin_qty <= coin_qty {
+                coin_qty -= lot.coin_qty;
+                cost += lot.pc_qty;
+                self.lots.pop_front();
+            } else {
+                let lot_cost = mul_div(lot.pc_qty, coin_qty as u128, lot.coin_qty as u128);
+                lot.coin_qty -= coin_qty;
+                lot.pc_qty -= lot_cost;
+                cost += lot_cost;
+                coin_qty = 0;
+            }
+        }
+        cost
+    }
+}
+
+/// computes `a * b / c` rounded down, `b` must not exceed `c` so the result does not exceed `a`
+fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    match a.checked_mul(b) {
+        Some(product) => product / c,
+        None => (a / c) * b + (a % c) * b / c,
+    }
+}
+
+/// computes `a - b` as an i128, saturating at its bounds
+fn signed_diff(a: u128, b: u128) -> i128 {
+    if a >= b {
+        i128::try_from(a - b).unwrap_or(i128::MAX)
+    } else {
+        i128::try_from(b - a).map_or(i128::MIN, |diff| -diff)
+    }
+}
+
+/// builds the position ledgers of cypher users from the cypher program's events
+///
+/// the events must be applied in the order they were emitted, events from other cypher groups are ignored
+#[derive(Debug, Clone)]
+pub struct PnlTracker {
+    pub cypher_group: Pubkey,
+    fees: FeeSchedule,
+    ledgers: HashMap<(Pubkey, Pubkey), PositionLedger>,
+}
+
+impl PnlTracker {
+    pub fn new(cypher_group_pk: Pubkey, group: &CypherGroup) -> Self {
+        Self {
+            cypher_group: cypher_group_pk,
+            fees: group.fee_schedule(),
+            ledgers: HashMap::new(),
+        }
+    }
+
+    /// gets the ledger for the given cypher user and mint
+    pub fn get_ledger(
+        &self,
+        cypher_user: &Pubkey,
+        c_asset_mint: &Pubkey,
+    ) -> Option<&PositionLedger> {
+        self.ledgers.get(&(*cypher_user, *c_asset_mint))
+    }
+
+    /// iterates over every ledger of the given cypher user
+    pub fn iter_user_ledgers<'a>(
+        &'a self,
+        cypher_user: &'a Pubkey,
+    ) -> impl Iterator<Item = &'a PositionLedger> {
+        self.ledgers
+            .values()
+            .filter(move |l| l.cypher_user == *cypher_user)
+    }
+
+    fn ledger_mut(&mut self, cypher_user: Pubkey, c_asset_mint: Pubkey) -> &mut PositionLedger {
+        self.ledgers
+            .entry((cypher_user, c_asset_mint))
+            .or_insert_with(|| PositionLedger::new(cypher_user, c_asset_mint))
+    }
+
+    /// applies a fill, the fee is the cypher fee on the fill's `pc_qty`, see [`FeeSchedule::get_order_fill_fee`]
+    pub fn apply_order_fill(&mut self, log: &OrderFillLog) {
+        if log.cypher_group != self.cypher_group {
+            return;
+        }
+        let fee = self.fees.get_order_fill_fee(log).fee;
+        let ledger = self.ledger_mut(log.cypher_user, log.c_asset_mint);
+        ledger.apply_trade(log.is_bid, log.coin_qty, log.pc_qty);
+        ledger.fees_paid = ledger.fees_paid.saturating_add(fee);
+    }
+
+    /// applies a deposit or withdrawal
+    ///
+    /// a cAsset deposit adds `amount` to the position and a withdrawal removes it, both valued at `market_price`,
+    /// the cAsset's market price when the event was emitted, `market_price` is ignored for the quote token
+    pub fn apply_deposit_or_withdraw(&mut self, log: &DepositOrWithdrawLog, market_price: u64) {
+        if log.cypher_group != self.cypher_group {
+            return;
+        }
+        let ledger = self.ledger_mut(log.cypher_user, log.c_asset_mint);
+        if log.is_deposit {
+            ledger.deposits = ledger.deposits.saturating_add(log.amount);
+        } else {
+            ledger.withdrawals = ledger.withdrawals.saturating_add(log.amount);
+        }
+        if log.c_asset_mint != quote_mint::ID {
+            let pc_qty = log.amount as u128 * market_price as u128;
+            ledger.apply_trade(log.is_deposit, log.amount, pc_qty);
+        }
+    }
+
+    /// applies the settlement of an executed market
+    ///
+    /// the logged deposits are sold and the logged borrows bought back at the execution price, which closes the
+    /// position when the ledger has seen every event of the user
+    pub fn apply_settle_position(&mut self, log: &SettlePositionLog) {
+        if log.cypher_group != self.cypher_group {
+            return;
+        }
+        let ledger = self.ledger_mut(log.cypher_user, log.c_asset_mint);
+        let price = log.execution_price as u128;
+        ledger.apply_trade(false, log.deposits, log.deposits as u128 * price);
+        ledger.apply_trade(true, log.borrows, log.borrows as u128 * price);
+    }
+
+    /// applies a liquidation to the liqee's ledgers
+    ///
+    /// the seized asset is sold and the repaid liability is bought back at the logged prices, unless they are the quote token,
+    /// the difference between the value seized and the value repaid is recorded as a liquidation loss on the asset's ledger
+    pub fn apply_liquidation(&mut self, log: &LiquidateMarginCollateralLog) {
+        if log.cypher_group != self.cypher_group {
+            return;
+        }
+        let asset_qty = log
+            .pre_asset_deposits
+            .saturating_sub(log.post_asset_deposits);
+        let liab_qty = log.pre_liab_borrows.saturating_sub(log.post_liab_borrows);
+        let asset_value = asset_qty as u128 * log.asset_price as u128;
+        let liab_value = liab_qty as u128 * log.liab_price as u128;
+
+        let asset_ledger = self.ledger_mut(log.liqee_user, log.asset_mint);
+        if log.asset_mint != quote_mint::ID {
+            asset_ledger.apply_trade(false, asset_qty, asset_value);
+        }
+        asset_ledger.liquidation_losses = asset_ledger
+            .liquidation_losses
+            .saturating_add(asset_value.saturating_sub(liab_value));
+
+        if log.liab_mint != quote_mint::ID {
+            let liab_ledger = self.ledger_mut(log.liqee_user, log.liab_mint);
+            liab_ledger.apply_trade(true, liab_qty, liab_value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::CypherGroupBuilder};
+
+    struct Fixture {
+        group: Box<CypherGroup>,
+        tracker: PnlTracker,
+        cypher_user: Pubkey,
+        c_asset_mint: Pubkey,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let c_asset_mint = Pubkey::new_unique();
+            let group = CypherGroupBuilder::new()
+                .market(0, c_asset_mint, 6, 100)
+                .build();
+            let tracker = PnlTracker::new(group.self_address, &group);
+            Self {
+                group,
+                tracker,
+                cypher_user: Pubkey::new_unique(),
+                c_asset_mint,
+            }
+        }
+
+        fn fill(&mut self, is_bid: bool, coin_qty: u64, pc_qty: u64) {
+            self.tracker.apply_order_fill(&OrderFillLog {
+                cypher_group: self.group.self_address,
+                cypher_user: self.cypher_user,
+                c_asset_mint: self.c_asset_mint,
+                is_taker: true,
+                is_bid,
+                client_order_id: 0,
+                coin_qty,
+                pc_qty,
+            });
+        }
+
+        fn ledger(&self) -> &PositionLedger {
+            self.tracker
+                .get_ledger(&self.cypher_user, &self.c_asset_mint)
+                .unwrap()
+        }
+
+        fn market(&self, price: u64) -> CypherMarket {
+            let mut market = self.group.markets[0];
+            market.market_price = price;
+            market
+        }
+    }
+
+    #[test]
+    fn fifo_and_average_cost() {
+        let mut f = Fixture::new();
+        f.fill(true, 10, 1_000);
+        f.fill(true, 10, 2_000);
+        f.fill(false, 10, 3_000);
+
+        let ledger = f.ledger();
+        assert_eq!(ledger.position, 10);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::Fifo), 2_000);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::AverageCost), 1_500);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::Fifo), 2_000);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::AverageCost), 1_500);
+        assert_eq!(ledger.average_entry_price(), Some(Number::from(150_u64)));
+        // the taker fee is 10 bps of each fill's quote amount
+        assert_eq!(ledger.fees_paid, 1 + 2 + 3);
+    }
+
+    #[test]
+    fn partial_close() {
+        let mut f = Fixture::new();
+        f.fill(false, 10, 1_000);
+        f.fill(true, 4, 320);
+
+        let ledger = f.ledger();
+        assert_eq!(ledger.position, -6);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::Fifo), 80);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::AverageCost), 80);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::Fifo), 600);
+        let market = f.market(90);
+        assert_eq!(ledger.unrealized_pnl(&market, CostBasisMethod::Fifo), 60);
+        assert_eq!(
+            ledger.unrealized_pnl(&market, CostBasisMethod::AverageCost),
+            60
+        );
+    }
+
+    #[test]
+    fn position_flip() {
+        let mut f = Fixture::new();
+        f.fill(true, 10, 1_000);
+        f.fill(false, 15, 1_800);
+
+        let ledger = f.ledger();
+        assert_eq!(ledger.position, -5);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::Fifo), 200);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::AverageCost), 200);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::Fifo), 600);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::AverageCost), 600);
+        assert_eq!(ledger.average_entry_price(), Some(Number::from(120_u64)));
+    }
+
+    #[test]
+    fn settlement() {
+        let mut f = Fixture::new();
+        f.fill(true, 10, 1_000);
+        f.tracker.apply_settle_position(&SettlePositionLog {
+            cypher_group: f.group.self_address,
+            cypher_user: f.cypher_user,
+            c_asset_mint: f.c_asset_mint,
+            deposits: 10,
+            borrows: 0,
+            execution_price: 150,
+        });
+        let ledger = f.ledger();
+        assert_eq!(ledger.position, 0);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::Fifo), 500);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::AverageCost), 500);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::Fifo), 0);
+        assert_eq!(ledger.average_entry_price(), None);
+
+        f.fill(false, 5, 500);
+        f.tracker.apply_settle_position(&SettlePositionLog {
+            cypher_group: f.group.self_address,
+            cypher_user: f.cypher_user,
+            c_asset_mint: f.c_asset_mint,
+            deposits: 0,
+            borrows: 5,
+            execution_price: 80,
+        });
+        let ledger = f.ledger();
+        assert_eq!(ledger.position, 0);
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::Fifo), 600);
+    }
+
+    #[test]
+    fn c_asset_deposits_change_position() {
+        let mut f = Fixture::new();
+        let transfer = |c_asset_mint: Pubkey, is_deposit: bool, amount: u64| DepositOrWithdrawLog {
+            cypher_group: f.group.self_address,
+            cypher_user: f.cypher_user,
+            c_asset_mint,
+            is_deposit,
+            amount,
+        };
+        let deposit = transfer(f.c_asset_mint, true, 10);
+        let withdrawal = transfer(f.c_asset_mint, false, 4);
+        let quote_deposit = transfer(quote_mint::ID, true, 1_000);
+
+        f.tracker.apply_deposit_or_withdraw(&deposit, 100);
+        f.tracker.apply_deposit_or_withdraw(&withdrawal, 150);
+        let ledger = f.ledger();
+        assert_eq!(ledger.position, 6);
+        assert_eq!((ledger.deposits, ledger.withdrawals), (10, 4));
+        assert_eq!(ledger.realized_pnl(CostBasisMethod::Fifo), 200);
+        assert_eq!(ledger.cost_basis(CostBasisMethod::Fifo), 600);
+
+        f.tracker.apply_deposit_or_withdraw(&quote_deposit, 100);
+        let quote_ledger = f
+            .tracker
+            .get_ledger(&f.cypher_user, &quote_mint::ID)
+            .unwrap();
+        assert_eq!(quote_ledger.position, 0);
+        assert_eq!(quote_ledger.deposits, 1_000);
+    }
+}
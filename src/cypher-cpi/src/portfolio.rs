@@ -0,0 +1,204 @@
+use {
+    crate::{constants::*, utils::checked_as_u64, CypherGroup, CypherUser, MarginResult},
+    jet_proto_math::Number,
+    std::collections::BTreeMap,
+};
+
+/// the combined exposure of several cypher users to a single market
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketExposure {
+    pub market_idx: usize,
+    /// the total base deposits
+    pub base_deposits: Number,
+    /// the total base borrows
+    pub base_borrows: Number,
+    /// the total coin amount locked in open orders, saturating at `u64::MAX`
+    pub oo_coin_total: u64,
+    /// the total pc amount locked in open orders, saturating at `u64::MAX`
+    pub oo_pc_total: u64,
+    /// the value of the open orders at the market price, including referrer rebates
+    pub oo_value: Number,
+}
+
+impl MarketExposure {
+    fn new(market_idx: usize) -> Self {
+        Self {
+            market_idx,
+            base_deposits: Number::ZERO,
+            base_borrows: Number::ZERO,
+            oo_coin_total: 0,
+            oo_pc_total: 0,
+            oo_value: Number::ZERO,
+        }
+    }
+
+    /// gets the net base position in native units, deposits minus borrows, `None` if it does not fit in a `u64`
+    pub fn net_base_position(&self) -> Option<i128> {
+        if self.base_deposits >= self.base_borrows {
+            checked_as_u64(self.base_deposits - self.base_borrows).map(i128::from)
+        } else {
+            checked_as_u64(self.base_borrows - self.base_deposits).map(|net| -i128::from(net))
+        }
+    }
+}
+
+/// a combined view over several cypher users, usually the sub-accounts of a single owner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Portfolio {
+    /// the total assets value, computed the same way as `CypherUser::get_assets_value`
+    pub assets_value: Number,
+    /// the total liabilities value, computed the same way as `CypherUser::get_liabilities_value`
+    pub liabilities_value: Number,
+    /// the total quote deposits, adjusted for the quote token's deposit index
+    pub quote_deposits: Number,
+    /// the total quote borrows, adjusted for the quote token's borrow index
+    pub quote_borrows: Number,
+    /// the combined exposure per market index, only markets with a position are present
+    pub markets: BTreeMap<usize, MarketExposure>,
+    /// the lowest margin c-ratio of the individual users, `Number::MAX` if there are no users
+    pub worst_c_ratio: Number,
+}
+
+impl Portfolio {
+    /// fails if any of the users' margin values can not be computed, see [`CypherUser::get_margin_c_ratio_components`]
+    pub fn new<'a>(
+        group: &CypherGroup,
+        users: impl IntoIterator<Item = &'a CypherUser>,
+    ) -> MarginResult<Self> {
+        let mut portfolio = Self {
+            assets_value: Number::ZERO,
+            liabilities_value: Number::ZERO,
+            quote_deposits: Number::ZERO,
+            quote_borrows: Number::ZERO,
+            markets: BTreeMap::new(),
+            worst_c_ratio: Number::MAX,
+        };
+        let quote_token = group.get_cypher_token(QUOTE_TOKEN_IDX);
+
+        for user in users {
+            let (c_ratio, assets_value, liabs_value) = user.get_margin_c_ratio_components(group)?;
+            portfolio.assets_value += assets_value;
+            portfolio.liabilities_value += liabs_value;
+            portfolio.worst_c_ratio = Number::min(portfolio.worst_c_ratio, c_ratio);
+
+            if let (Some(position), Some(quote_token)) =
+                (user.get_position(QUOTE_TOKEN_IDX), quote_token)
+            {
+                portfolio.quote_deposits += position.total_deposits(quote_token);
+                portfolio.quote_borrows += position.total_borrows(quote_token);
+            }
+
+            for position in user.iter_positions() {
+                let market_idx = position.market_idx as usize;
+                let market_price = if let Some(m) = group.get_cypher_market(market_idx) {
+                    m.market_price
+                } else {
+                    continue;
+                };
+                let exposure = portfolio
+                    .markets
+                    .entry(market_idx)
+                    .or_insert_with(|| MarketExposure::new(market_idx));
+                exposure.base_deposits += position.base_deposits();
+                exposure.base_borrows += position.base_borrows();
+
+                let oo_info = &position.oo_info;
+                if oo_info.is_account_open {
+                    exposure.oo_coin_total =
+                        exposure.oo_coin_total.saturating_add(oo_info.coin_total);
+                    exposure.oo_pc_total = exposure.oo_pc_total.saturating_add(oo_info.pc_total);
+                    let oo_coin_value = oo_info.coin_total * market_price;
+                    let oo_value =
+                        oo_coin_value + oo_info.pc_total + oo_info.referrer_rebates_accrued;
+                    exposure.oo_value += oo_value.into();
+                }
+            }
+        }
+        Ok(portfolio)
+    }
+
+    /// gets the combined margin c-ratio of all the users, as if they were a single account
+    pub fn c_ratio(&self) -> Number {
+        if self.liabilities_value == Number::ZERO {
+            Number::MAX
+        } else {
+            self.assets_value / self.liabilities_value
+        }
+    }
+
+    /// gets the net base position for the given market index in native units, zero if there is no position,
+    /// `None` if it does not fit in a `u64`
+    pub fn net_base_position(&self, market_idx: usize) -> Option<i128> {
+        self.markets
+            .get(&market_idx)
+            .map_or(Some(0), |e| e.net_base_position())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*, anchor_lang::prelude::Pubkey};
+
+    #[test]
+    fn aggregates_users() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let first = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(QUOTE_TOKEN_IDX, 100)
+            .deposit(0, 1_000)
+            .open_orders(0, 10, 50)
+            .build();
+        let second = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(QUOTE_TOKEN_IDX, 1_000_000)
+            .borrow(0, 300)
+            .build();
+
+        let portfolio = Portfolio::new(&group, [&*first, &*second]).unwrap();
+        assert_eq!(portfolio.quote_deposits, Number::from(1_000_100_u64));
+        assert_eq!(portfolio.quote_borrows, Number::ZERO);
+        // 100 + 10 * 40 + 50 + 1_000 * 40 for the first user, 1_000_000 for the second
+        assert_eq!(portfolio.assets_value, Number::from(1_040_550_u64));
+        assert_eq!(portfolio.liabilities_value, Number::from(12_000_u64));
+        assert_eq!(
+            portfolio.c_ratio(),
+            Number::from(1_040_550_u64) / Number::from(12_000_u64)
+        );
+        assert_eq!(
+            portfolio.worst_c_ratio,
+            second.get_margin_c_ratio(&group).unwrap()
+        );
+
+        assert_eq!(portfolio.markets.len(), 1);
+        let exposure = portfolio.markets[&0];
+        assert_eq!(exposure.base_deposits, Number::from(1_000_u64));
+        assert_eq!(exposure.base_borrows, Number::from(300_u64));
+        assert_eq!((exposure.oo_coin_total, exposure.oo_pc_total), (10, 50));
+        assert_eq!(exposure.oo_value, Number::from(450_u64));
+        assert_eq!(portfolio.net_base_position(0), Some(700));
+        assert_eq!(portfolio.net_base_position(1), Some(0));
+    }
+
+    #[test]
+    fn empty_portfolio() {
+        let group = CypherGroupBuilder::new().build();
+        let portfolio = Portfolio::new(&group, std::iter::empty()).unwrap();
+        assert_eq!(portfolio.assets_value, Number::ZERO);
+        assert_eq!(portfolio.worst_c_ratio, Number::MAX);
+        assert_eq!(portfolio.c_ratio(), Number::MAX);
+        assert!(portfolio.markets.is_empty());
+    }
+
+    #[test]
+    fn net_base_position_is_signed_and_checked() {
+        let mut exposure = MarketExposure::new(0);
+        exposure.base_deposits = Number::from(300_u64);
+        exposure.base_borrows = Number::from(1_000_u64);
+        assert_eq!(exposure.net_base_position(), Some(-700));
+
+        exposure.base_borrows = Number::from(u64::MAX) * 4_u64;
+        assert_eq!(exposure.net_base_position(), None);
+        exposure.base_deposits = Number::from(u64::MAX) * 4_u64;
+        assert_eq!(exposure.net_base_position(), Some(0));
+    }
+}
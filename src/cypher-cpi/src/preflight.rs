@@ -0,0 +1,570 @@
+use {
+    crate::{
+        constants::*, signer::UserAction, utils::derive_open_orders_address, CypherGroup,
+        CypherUser,
+    },
+    anchor_discriminator::get_ix_data,
+    anchor_lang::{prelude::*, solana_program::instruction::Instruction},
+    serum_dex::instruction::MarketInstruction,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightError {
+    /// the instruction is not addressed to the cypher program
+    InvalidProgramId { found: Pubkey },
+    /// the instruction data does not match any instruction built by `client`
+    UnknownInstruction,
+    /// the instruction has less accounts than expected
+    MissingAccount { index: usize },
+    /// the cypher group account does not match the given group
+    GroupMismatch { expected: Pubkey, found: Pubkey },
+    /// the cypher user account does not match the given user
+    UserMismatch { expected: Pubkey, found: Pubkey },
+    /// the cypher user belongs to a different cypher group
+    UserGroupMismatch { expected: Pubkey, found: Pubkey },
+    /// the user signer is neither the owner nor the delegate of the cypher user,
+    /// or it is the delegate and the instruction requires the owner
+    InvalidSigner { found: Pubkey },
+    /// the c asset mint or the dex market could not be found in the cypher group
+    UnableToFindTokenOrMarket { found: Pubkey },
+    /// the c asset vault does not belong to the market
+    CAssetVaultMismatch { expected: Pubkey, found: Pubkey },
+    /// the pc vault is not the group's quote vault
+    PcVaultMismatch { expected: Pubkey, found: Pubkey },
+    /// the dex market does not belong to the market
+    DexMarketMismatch { expected: Pubkey, found: Pubkey },
+    /// the open orders account is not the one derived for the cypher user and the dex market
+    OpenOrdersMismatch { expected: Pubkey, found: Pubkey },
+}
+
+/// the positions of the accounts checked by the pre-flight validation in an instruction's account list
+#[derive(Default)]
+struct AccountRoles {
+    action: Option<UserAction>,
+    cypher_group: Option<usize>,
+    cypher_user: Option<usize>,
+    user_signer: Option<usize>,
+    c_asset_mint: Option<usize>,
+    cypher_c_asset_vault: Option<usize>,
+    cypher_pc_vault: Option<usize>,
+    dex_market: Option<usize>,
+    open_orders: Option<usize>,
+}
+
+impl AccountRoles {
+    fn user(
+        action: UserAction,
+        cypher_group: usize,
+        cypher_user: usize,
+        user_signer: usize,
+    ) -> Self {
+        Self {
+            action: Some(action),
+            cypher_group: Some(cypher_group),
+            cypher_user: Some(cypher_user),
+            user_signer: Some(user_signer),
+            ..Default::default()
+        }
+    }
+
+    fn from_anchor_ix(data: &[u8]) -> Option<Self> {
+        let roles = [
+            (
+                "close_cypher_user",
+                Self::user(UserAction::CloseAccount, 0, 1, 2),
+            ),
+            ("set_delegate", Self::user(UserAction::SetDelegate, 0, 1, 2)),
+            (
+                "deposit_collateral",
+                Self {
+                    cypher_pc_vault: Some(3),
+                    ..Self::user(UserAction::Deposit, 0, 1, 2)
+                },
+            ),
+            (
+                "withdraw_collateral",
+                Self {
+                    cypher_pc_vault: Some(4),
+                    ..Self::user(UserAction::Withdraw, 0, 2, 3)
+                },
+            ),
+            (
+                "liquidate_collateral",
+                Self::user(UserAction::Liquidate, 0, 1, 2),
+            ),
+            (
+                "settle_position",
+                Self {
+                    cypher_group: Some(0),
+                    cypher_user: Some(1),
+                    c_asset_mint: Some(2),
+                    ..Default::default()
+                },
+            ),
+        ];
+        let discriminator = data.get(..8)?;
+        roles
+            .into_iter()
+            .find(|(name, _)| get_ix_data(name, vec![]) == discriminator)
+            .map(|(_, roles)| roles)
+    }
+
+    fn from_dex_ix(data: &[u8]) -> Option<Self> {
+        let trade = |action: UserAction, dex_market: usize, open_orders: usize| Self {
+            c_asset_mint: Some(dex_market - 3),
+            cypher_c_asset_vault: Some(dex_market - 2),
+            cypher_pc_vault: Some(dex_market - 1),
+            dex_market: Some(dex_market),
+            open_orders: Some(open_orders),
+            ..Self::user(action, 0, dex_market - 5, dex_market - 4)
+        };
+        match MarketInstruction::unpack(data)? {
+            MarketInstruction::InitOpenOrders => Some(Self {
+                dex_market: Some(4),
+                open_orders: Some(6),
+                ..Self::user(UserAction::InitOpenOrders, 0, 1, 2)
+            }),
+            MarketInstruction::CloseOpenOrders => Some(Self {
+                dex_market: Some(3),
+                open_orders: Some(4),
+                ..Self::user(UserAction::CloseOpenOrders, 0, 1, 2)
+            }),
+            MarketInstruction::NewOrderV3(_) => Some(trade(UserAction::NewOrder, 8, 9)),
+            MarketInstruction::CancelOrderV2(_)
+            | MarketInstruction::CancelOrderByClientIdV2(_)
+            | MarketInstruction::Prune(_) => Some(trade(UserAction::CancelOrder, 7, 11)),
+            MarketInstruction::SettleFunds => Some(trade(UserAction::SettleFunds, 7, 8)),
+            _ => None,
+        }
+    }
+}
+
+/// the fetched accounts an instruction is checked against
+pub struct PreflightContext<'a> {
+    pub cypher_group_pk: Pubkey,
+    pub cypher_group: &'a CypherGroup,
+    pub cypher_user_pk: Pubkey,
+    pub cypher_user: &'a CypherUser,
+}
+
+impl<'a> PreflightContext<'a> {
+    pub fn new(
+        cypher_group_pk: Pubkey,
+        cypher_group: &'a CypherGroup,
+        cypher_user_pk: Pubkey,
+        cypher_user: &'a CypherUser,
+    ) -> Self {
+        Self {
+            cypher_group_pk,
+            cypher_group,
+            cypher_user_pk,
+            cypher_user,
+        }
+    }
+
+    /// validates an instruction built by `client` against the fetched accounts
+    ///
+    /// returns every problem found, an empty list means the instruction is consistent with the accounts
+    pub fn validate(&self, ix: &Instruction) -> Vec<PreflightError> {
+        let mut errors = Vec::new();
+        if ix.program_id != crate::id() {
+            errors.push(PreflightError::InvalidProgramId {
+                found: ix.program_id,
+            });
+            return errors;
+        }
+        let roles = match AccountRoles::from_anchor_ix(&ix.data)
+            .or_else(|| AccountRoles::from_dex_ix(&ix.data))
+        {
+            Some(roles) => roles,
+            None => {
+                errors.push(PreflightError::UnknownInstruction);
+                return errors;
+            }
+        };
+        let account = |idx: Option<usize>, errors: &mut Vec<PreflightError>| {
+            let idx = idx?;
+            match ix.accounts.get(idx) {
+                Some(meta) => Some(meta.pubkey),
+                None => {
+                    errors.push(PreflightError::MissingAccount { index: idx });
+                    None
+                }
+            }
+        };
+
+        if let Some(cypher_group) = account(roles.cypher_group, &mut errors) {
+            if cypher_group != self.cypher_group_pk {
+                errors.push(PreflightError::GroupMismatch {
+                    expected: self.cypher_group_pk,
+                    found: cypher_group,
+                });
+            }
+        }
+        if self.cypher_user.cypher_group != self.cypher_group_pk {
+            errors.push(PreflightError::UserGroupMismatch {
+                expected: self.cypher_group_pk,
+                found: self.cypher_user.cypher_group,
+            });
+        }
+        if let Some(cypher_user) = account(roles.cypher_user, &mut errors) {
+            if cypher_user != self.cypher_user_pk {
+                errors.push(PreflightError::UserMismatch {
+                    expected: self.cypher_user_pk,
+                    found: cypher_user,
+                });
+            }
+        }
+        if let (Some(user_signer), Some(action)) =
+            (account(roles.user_signer, &mut errors), roles.action)
+        {
+            if !self.cypher_user.can_sign(&user_signer, action) {
+                errors.push(PreflightError::InvalidSigner { found: user_signer });
+            }
+        }
+        if let Some(cypher_pc_vault) = account(roles.cypher_pc_vault, &mut errors) {
+            let expected = self.cypher_group.quote_vault();
+            if cypher_pc_vault != expected {
+                errors.push(PreflightError::PcVaultMismatch {
+                    expected,
+                    found: cypher_pc_vault,
+                });
+            }
+        }
+
+        let c_asset_mint = account(roles.c_asset_mint, &mut errors);
+        let dex_market = account(roles.dex_market, &mut errors);
+        // the market's slot is looked up in the raw arrays, as the group may have empty slots between listed markets
+        let market_idx = if let Some(c_asset_mint) = c_asset_mint {
+            self.cypher_group.tokens[..MARKETS_MAX_CNT]
+                .iter()
+                .position(|t| t.mint == c_asset_mint && c_asset_mint != Pubkey::default())
+                .ok_or(PreflightError::UnableToFindTokenOrMarket {
+                    found: c_asset_mint,
+                })
+        } else if let Some(dex_market) = dex_market {
+            self.cypher_group
+                .markets
+                .iter()
+                .position(|m| m.dex_market == dex_market && dex_market != Pubkey::default())
+                .ok_or(PreflightError::UnableToFindTokenOrMarket { found: dex_market })
+        } else {
+            return errors;
+        };
+        let market_idx = match market_idx {
+            Ok(market_idx) => market_idx,
+            Err(e) => {
+                errors.push(e);
+                return errors;
+            }
+        };
+
+        if let Some(cypher_c_asset_vault) = account(roles.cypher_c_asset_vault, &mut errors) {
+            let expected = self.cypher_group.tokens[market_idx].vault;
+            if cypher_c_asset_vault != expected {
+                errors.push(PreflightError::CAssetVaultMismatch {
+                    expected,
+                    found: cypher_c_asset_vault,
+                });
+            }
+        }
+        let expected_dex_market = self.cypher_group.markets[market_idx].dex_market;
+        if let Some(dex_market) = dex_market {
+            if dex_market != expected_dex_market {
+                errors.push(PreflightError::DexMarketMismatch {
+                    expected: expected_dex_market,
+                    found: dex_market,
+                });
+            }
+        }
+        if let Some(open_orders) = account(roles.open_orders, &mut errors) {
+            let (expected, _) =
+                derive_open_orders_address(&expected_dex_market, &self.cypher_user_pk);
+            if open_orders != expected {
+                errors.push(PreflightError::OpenOrdersMismatch {
+                    expected,
+                    found: open_orders,
+                });
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            client::{
+                deposit_collateral_ix, settle_funds_ix, settle_position_ix, withdraw_collateral_ix,
+            },
+            signer::UserSigner,
+            test_utils::*,
+        },
+    };
+
+    /// the market is listed at slot 2, with empty slots before it
+    const MARKET_IDX: usize = 2;
+
+    struct Fixture {
+        group: Box<CypherGroup>,
+        user: Box<CypherUser>,
+        cypher_user: Pubkey,
+        owner: Pubkey,
+        delegate: Pubkey,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let group = CypherGroupBuilder::new()
+                .market(MARKET_IDX, Pubkey::new_unique(), 6, 40)
+                .build();
+            let owner = Pubkey::new_unique();
+            let delegate = Pubkey::new_unique();
+            let user = CypherUserBuilder::new(group.self_address, owner)
+                .delegate(delegate)
+                .build();
+            Self {
+                group,
+                user,
+                cypher_user: Pubkey::new_unique(),
+                owner,
+                delegate,
+            }
+        }
+
+        fn validate(&self, ix: &Instruction) -> Vec<PreflightError> {
+            PreflightContext::new(
+                self.group.self_address,
+                &self.group,
+                self.cypher_user,
+                &self.user,
+            )
+            .validate(ix)
+        }
+
+        fn deposit_ix(&self, cypher_pc_vault: &Pubkey) -> Instruction {
+            deposit_collateral_ix(
+                &self.group.self_address,
+                &self.cypher_user,
+                cypher_pc_vault,
+                &UserSigner::Owner(self.owner),
+                &Pubkey::new_unique(),
+                100,
+            )
+        }
+
+        fn settle_funds_ix(
+            &self,
+            c_asset_mint: &Pubkey,
+            cypher_c_asset_vault: &Pubkey,
+            dex_market: &Pubkey,
+            open_orders: &Pubkey,
+        ) -> Instruction {
+            settle_funds_ix(
+                &self.group.self_address,
+                &self.group.vault_signer,
+                &self.cypher_user,
+                &UserSigner::Delegate(self.delegate),
+                c_asset_mint,
+                cypher_c_asset_vault,
+                &self.group.quote_vault(),
+                dex_market,
+                open_orders,
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+            )
+        }
+
+        fn market_accounts(&self) -> (Pubkey, Pubkey, Pubkey, Pubkey) {
+            let dex_market = self.group.markets[MARKET_IDX].dex_market;
+            let (open_orders, _) = derive_open_orders_address(&dex_market, &self.cypher_user);
+            (
+                self.group.tokens[MARKET_IDX].mint,
+                self.group.tokens[MARKET_IDX].vault,
+                dex_market,
+                open_orders,
+            )
+        }
+    }
+
+    #[test]
+    fn accepts_market_after_empty_slots() {
+        let f = Fixture::new();
+        let (c_asset_mint, c_asset_vault, dex_market, open_orders) = f.market_accounts();
+        let ix = f.settle_funds_ix(&c_asset_mint, &c_asset_vault, &dex_market, &open_orders);
+        assert_eq!(f.validate(&ix), vec![]);
+        let ix = settle_position_ix(&f.group.self_address, &f.cypher_user, &c_asset_mint);
+        assert_eq!(f.validate(&ix), vec![]);
+        assert_eq!(f.validate(&f.deposit_ix(&f.group.quote_vault())), vec![]);
+    }
+
+    #[test]
+    fn rejects_foreign_program_and_unknown_instruction() {
+        let f = Fixture::new();
+        let mut ix = f.deposit_ix(&f.group.quote_vault());
+        ix.program_id = Pubkey::new_unique();
+        assert_eq!(
+            f.validate(&ix),
+            vec![PreflightError::InvalidProgramId {
+                found: ix.program_id
+            }]
+        );
+        let mut ix = f.deposit_ix(&f.group.quote_vault());
+        ix.data = vec![0xff; 8];
+        assert_eq!(f.validate(&ix), vec![PreflightError::UnknownInstruction]);
+    }
+
+    #[test]
+    fn rejects_missing_account() {
+        let f = Fixture::new();
+        let mut ix = f.deposit_ix(&f.group.quote_vault());
+        ix.accounts.truncate(3);
+        assert_eq!(
+            f.validate(&ix),
+            vec![PreflightError::MissingAccount { index: 3 }]
+        );
+    }
+
+    #[test]
+    fn rejects_group_and_user_mismatches() {
+        let f = Fixture::new();
+        let other = Pubkey::new_unique();
+        let ix = deposit_collateral_ix(
+            &other,
+            &other,
+            &f.group.quote_vault(),
+            &UserSigner::Owner(f.owner),
+            &Pubkey::new_unique(),
+            100,
+        );
+        assert_eq!(
+            f.validate(&ix),
+            vec![
+                PreflightError::GroupMismatch {
+                    expected: f.group.self_address,
+                    found: other
+                },
+                PreflightError::UserMismatch {
+                    expected: f.cypher_user,
+                    found: other
+                },
+            ]
+        );
+
+        let mut f = Fixture::new();
+        f.user.cypher_group = other;
+        assert_eq!(
+            f.validate(&f.deposit_ix(&f.group.quote_vault())),
+            vec![PreflightError::UserGroupMismatch {
+                expected: f.group.self_address,
+                found: other
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_signer() {
+        let f = Fixture::new();
+        let withdraw_ix = |user_signer: Pubkey| {
+            withdraw_collateral_ix(
+                &f.group.self_address,
+                &f.cypher_user,
+                &f.group.quote_vault(),
+                &f.group.vault_signer,
+                &UserSigner::Owner(user_signer),
+                &Pubkey::new_unique(),
+                100,
+            )
+            .unwrap()
+        };
+        assert_eq!(f.validate(&withdraw_ix(f.owner)), vec![]);
+        // the delegate is passed as the owner, which the builder can not tell apart
+        assert_eq!(
+            f.validate(&withdraw_ix(f.delegate)),
+            vec![PreflightError::InvalidSigner { found: f.delegate }]
+        );
+        let stranger = Pubkey::new_unique();
+        assert_eq!(
+            f.validate(&withdraw_ix(stranger)),
+            vec![PreflightError::InvalidSigner { found: stranger }]
+        );
+    }
+
+    #[test]
+    fn rejects_vault_mismatches() {
+        let f = Fixture::new();
+        let pc_vault = Pubkey::new_unique();
+        assert_eq!(
+            f.validate(&f.deposit_ix(&pc_vault)),
+            vec![PreflightError::PcVaultMismatch {
+                expected: f.group.quote_vault(),
+                found: pc_vault
+            }]
+        );
+
+        let (c_asset_mint, c_asset_vault, dex_market, open_orders) = f.market_accounts();
+        let wrong_vault = Pubkey::new_unique();
+        let ix = f.settle_funds_ix(&c_asset_mint, &wrong_vault, &dex_market, &open_orders);
+        assert_eq!(
+            f.validate(&ix),
+            vec![PreflightError::CAssetVaultMismatch {
+                expected: c_asset_vault,
+                found: wrong_vault
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_market_mismatches() {
+        let f = Fixture::new();
+        let (c_asset_mint, c_asset_vault, dex_market, open_orders) = f.market_accounts();
+
+        let unknown_mint = Pubkey::new_unique();
+        let ix = settle_position_ix(&f.group.self_address, &f.cypher_user, &unknown_mint);
+        assert_eq!(
+            f.validate(&ix),
+            vec![PreflightError::UnableToFindTokenOrMarket {
+                found: unknown_mint
+            }]
+        );
+
+        let wrong_market = Pubkey::new_unique();
+        let (wrong_open_orders, _) = derive_open_orders_address(&wrong_market, &f.cypher_user);
+        let ix = f.settle_funds_ix(
+            &c_asset_mint,
+            &c_asset_vault,
+            &wrong_market,
+            &wrong_open_orders,
+        );
+        assert_eq!(
+            f.validate(&ix),
+            vec![
+                PreflightError::DexMarketMismatch {
+                    expected: dex_market,
+                    found: wrong_market
+                },
+                PreflightError::OpenOrdersMismatch {
+                    expected: open_orders,
+                    found: wrong_open_orders
+                },
+            ]
+        );
+
+        let ix = f.settle_funds_ix(
+            &c_asset_mint,
+            &c_asset_vault,
+            &dex_market,
+            &wrong_open_orders,
+        );
+        assert_eq!(
+            f.validate(&ix),
+            vec![PreflightError::OpenOrdersMismatch {
+                expected: open_orders,
+                found: wrong_open_orders
+            }]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use {
+    crate::{constants::*, CypherGroup, CypherUser},
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+};
+
+/// the deposits, borrows and utilization of a single token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenRisk {
+    pub token_idx: usize,
+    pub mint: Pubkey,
+    /// the total deposits, adjusted for the token's deposit index
+    pub total_deposits: Number,
+    /// the total borrows, adjusted for the token's borrow index
+    pub total_borrows: Number,
+    /// the total borrows over the total deposits
+    pub utilization: Number,
+}
+
+/// the open interest of a single market, in native units of the c asset, summed as numbers so that it cannot overflow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketRisk {
+    pub market_idx: usize,
+    /// the sum of the users' net long positions
+    pub long_open_interest: Number,
+    /// the sum of the users' net short positions
+    pub short_open_interest: Number,
+    /// the short open interest valued at the market price, in native quote units
+    pub open_interest_value: Number,
+}
+
+/// the distribution of the users' margin c-ratios against the group's margin ratios
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CRatioDistribution {
+    /// the c-ratios of the users with liabilities, lowest first
+    pub c_ratios: Vec<Number>,
+    /// the users without liabilities
+    pub no_liabilities: usize,
+    /// the users below the maintenance ratio, which can be liquidated
+    pub below_maint: usize,
+    /// the users at or above the maintenance ratio but below the partial ratio
+    pub below_partial: usize,
+    /// the users at or above the partial ratio but below the initialization ratio
+    pub below_init: usize,
+    /// the users at or above the initialization ratio
+    pub above_init: usize,
+    /// the users whose margin values could not be computed
+    pub errors: usize,
+}
+
+impl CRatioDistribution {
+    /// gets the c-ratio at the given percentile of the users with liabilities, `None` if there are none
+    pub fn percentile(&self, percentile: u8) -> Option<Number> {
+        if self.c_ratios.is_empty() {
+            return None;
+        }
+        let idx = (self.c_ratios.len() - 1) * percentile.min(100) as usize / 100;
+        self.c_ratios.get(idx).copied()
+    }
+}
+
+/// protocol-level risk metrics of a cypher group and a set of its users
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupRiskReport {
+    pub tokens: Vec<TokenRisk>,
+    pub markets: Vec<MarketRisk>,
+    pub insurance_fund: u64,
+    pub protocol_fee: u64,
+    pub c_ratios: CRatioDistribution,
+}
+
+impl GroupRiskReport {
+    pub fn new<'a>(group: &CypherGroup, users: impl IntoIterator<Item = &'a CypherUser>) -> Self {
+        let tokens = (0..TOKENS_MAX_CNT)
+            .filter_map(|idx| {
+                group.get_cypher_token(idx).map(|token| TokenRisk {
+                    token_idx: idx,
+                    mint: token.mint,
+                    total_deposits: token.total_deposits(),
+                    total_borrows: token.total_borrows(),
+                    utilization: token.utilization(),
+                })
+            })
+            .collect();
+        let mut markets: Vec<MarketRisk> = (0..MARKETS_MAX_CNT)
+            .filter(|idx| group.get_cypher_market(*idx).is_some())
+            .map(|market_idx| MarketRisk {
+                market_idx,
+                long_open_interest: Number::ZERO,
+                short_open_interest: Number::ZERO,
+                open_interest_value: Number::ZERO,
+            })
+            .collect();
+
+        let margin_maint_ratio = group.margin_maint_ratio();
+        let margin_partial_ratio = group.margin_partial_ratio();
+        let margin_init_ratio = group.margin_init_ratio();
+        let mut c_ratios = CRatioDistribution::default();
+        for user in users {
+            for position in user.iter_positions() {
+                let market_risk = match markets
+                    .iter_mut()
+                    .find(|m| m.market_idx == position.market_idx as usize)
+                {
+                    Some(market_risk) => market_risk,
+                    None => continue,
+                };
+                let deposits = position.base_deposits();
+                let borrows = position.base_borrows();
+                if deposits > borrows {
+                    market_risk.long_open_interest =
+                        market_risk.long_open_interest + (deposits - borrows);
+                } else {
+                    market_risk.short_open_interest =
+                        market_risk.short_open_interest + (borrows - deposits);
+                }
+            }
+
+            let (c_ratio, _, liabs_value) = match user.get_margin_c_ratio_components(group) {
+                Ok(components) => components,
+                Err(_) => {
+                    c_ratios.errors += 1;
+                    continue;
+                }
+            };
+            if liabs_value == Number::ZERO {
+                c_ratios.no_liabilities += 1;
+                continue;
+            }
+            if c_ratio < margin_maint_ratio {
+                c_ratios.below_maint += 1;
+            } else if c_ratio < margin_partial_ratio {
+                c_ratios.below_partial += 1;
+            } else if c_ratio < margin_init_ratio {
+                c_ratios.below_init += 1;
+            } else {
+                c_ratios.above_init += 1;
+            }
+            c_ratios.c_ratios.push(c_ratio);
+        }
+        c_ratios.c_ratios.sort_unstable();
+
+        for market_risk in markets.iter_mut() {
+            let market_price = group
+                .get_cypher_market(market_risk.market_idx)
+                .expect("only the listed markets are reported")
+                .market_price;
+            market_risk.open_interest_value = market_risk.short_open_interest * market_price;
+        }
+
+        Self {
+            tokens,
+            markets,
+            insurance_fund: group.insurance_fund,
+            protocol_fee: group.protocol_fee,
+            c_ratios,
+        }
+    }
+}
+
+impl CypherGroup {
+    /// builds the risk report of the group over the given users, see [`GroupRiskReport`]
+    pub fn risk_report<'a>(
+        &self,
+        users: impl IntoIterator<Item = &'a CypherUser>,
+    ) -> GroupRiskReport {
+        GroupRiskReport::new(self, users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*};
+
+    fn group() -> Box<CypherGroup> {
+        CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .market(1, Pubkey::new_unique(), 6, 10)
+            .token_balances(
+                QUOTE_TOKEN_IDX,
+                Number::from(1_000_u64),
+                Number::from(250_u64),
+            )
+            .token_indices(QUOTE_TOKEN_IDX, Number::ONE, Number::from_percent(120_u16))
+            .fund(500, 20)
+            .build()
+    }
+
+    fn user(group: &CypherGroup) -> CypherUserBuilder {
+        CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+    }
+
+    #[test]
+    fn empty_users() {
+        let group = group();
+        let report = group.risk_report(std::iter::empty());
+        let tokens: Vec<usize> = report.tokens.iter().map(|t| t.token_idx).collect();
+        assert_eq!(tokens, vec![0, 1, QUOTE_TOKEN_IDX]);
+        let quote = report.tokens[2];
+        assert_eq!(quote.total_borrows, Number::from(300_u64));
+        assert_eq!(quote.utilization, Number::from_percent(30_u16));
+        assert_eq!(report.tokens[0].utilization, Number::ZERO);
+        assert!(
+            report
+                .markets
+                .iter()
+                .all(|m| m.long_open_interest == Number::ZERO
+                    && m.short_open_interest == Number::ZERO)
+        );
+        assert_eq!((report.insurance_fund, report.protocol_fee), (500, 20));
+        assert_eq!(report.c_ratios, CRatioDistribution::default());
+        assert_eq!(report.c_ratios.percentile(50), None);
+    }
+
+    #[test]
+    fn single_user() {
+        let group = group();
+        let user = user(&group)
+            .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+            .borrow(0, 2_000_000)
+            .build();
+        let report = group.risk_report([user.as_ref()]);
+        assert_eq!(
+            report.markets[0],
+            MarketRisk {
+                market_idx: 0,
+                long_open_interest: Number::ZERO,
+                short_open_interest: Number::from(2_000_000_u64),
+                open_interest_value: Number::from(80_000_000_u64),
+            }
+        );
+        assert_eq!(report.markets[1].short_open_interest, Number::ZERO);
+        let c_ratio = Number::from_percent(125_u16);
+        assert_eq!(report.c_ratios.c_ratios, vec![c_ratio]);
+        assert_eq!(report.c_ratios.below_partial, 1);
+        for percentile in [0, 50, 100] {
+            assert_eq!(report.c_ratios.percentile(percentile), Some(c_ratio));
+        }
+    }
+
+    #[test]
+    fn multiple_users() {
+        let group = group();
+        let users = [
+            // 125%, below the partial ratio
+            user(&group)
+                .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+                .borrow(0, 2_000_000)
+                .build(),
+            // 104%, below the maintenance ratio
+            user(&group)
+                .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+                .borrow(0, 2_400_000)
+                .build(),
+            // 200%, above the initialization ratio
+            user(&group)
+                .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+                .borrow(1, 5_000_000)
+                .build(),
+            user(&group).deposit(0, 1_000_000).build(),
+            // a borrow in a market which is not listed
+            user(&group)
+                .deposit(QUOTE_TOKEN_IDX, 100)
+                .borrow(2, 1)
+                .build(),
+        ];
+        let report = group.risk_report(users.iter().map(|u| u.as_ref()));
+
+        assert_eq!(report.markets.len(), 2);
+        assert_eq!(
+            report.markets[0].long_open_interest,
+            Number::from(1_000_000_u64)
+        );
+        assert_eq!(
+            report.markets[0].short_open_interest,
+            Number::from(4_400_000_u64)
+        );
+        assert_eq!(
+            report.markets[1].short_open_interest,
+            Number::from(5_000_000_u64)
+        );
+        assert_eq!(
+            report.markets[1].open_interest_value,
+            Number::from(50_000_000_u64)
+        );
+
+        let c_ratios = &report.c_ratios;
+        let lowest = users[1].get_margin_c_ratio(&group).unwrap();
+        assert_eq!(
+            c_ratios.c_ratios,
+            vec![
+                lowest,
+                Number::from_percent(125_u16),
+                Number::from_percent(200_u16)
+            ]
+        );
+        assert_eq!(
+            (
+                c_ratios.below_maint,
+                c_ratios.below_partial,
+                c_ratios.below_init,
+                c_ratios.above_init
+            ),
+            (1, 1, 0, 1)
+        );
+        assert_eq!((c_ratios.no_liabilities, c_ratios.errors), (1, 1));
+        assert_eq!(c_ratios.percentile(0), Some(lowest));
+        assert_eq!(c_ratios.percentile(50), Some(Number::from_percent(125_u16)));
+        assert_eq!(
+            c_ratios.percentile(100),
+            Some(Number::from_percent(200_u16))
+        );
+        assert_eq!(
+            c_ratios.percentile(200),
+            Some(Number::from_percent(200_u16))
+        );
+    }
+}
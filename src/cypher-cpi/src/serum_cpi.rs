@@ -2,17 +2,39 @@
 use {
     crate::client::{
         cancel_order_by_client_id_v2_ix, cancel_order_v2_ix, close_open_orders_ix,
-        init_open_orders_ix, new_order_v3_ix, settle_funds_ix,
+        consume_events_ix, init_open_orders_ix, new_order_v3_ix, prune_ix, settle_funds_ix,
     },
     crate::cpi::accounts::{
         NoOpCancelOrder as CancelOrderCpi, NoOpCloseOpenOrders as CloseOpenOrdersCpi,
         NoOpInitOpenOrders as InitOpenOrdersCpi, NoOpNewOrderV3 as NewOrderV3Cpi,
         NoOpSettleFunds as SettleFundsCpi,
     },
+    crate::signer::UserSigner,
     anchor_lang::{prelude::*, solana_program::program::invoke_signed},
     serum_dex::instruction::{CancelOrderInstructionV2, NewOrderInstructionV3},
 };
 
+/// accounts for a permissioned consume events call
+///
+/// the cypher users and their open orders accounts are variable-length lists and must be passed as remaining accounts,
+/// first all the cypher users and then their open orders accounts in the same order, e.g. `[user_a, user_b, oo_a, oo_b]`
+#[derive(Accounts)]
+pub struct ConsumeEvents<'info> {
+    /// CHECK: checked by the cypher program
+    #[account(mut)]
+    pub cypher_group: AccountInfo<'info>,
+    /// CHECK: checked by the dex program
+    #[account(mut)]
+    pub dex_market: AccountInfo<'info>,
+    /// CHECK: checked by the dex program
+    #[account(mut)]
+    pub event_queue: AccountInfo<'info>,
+    /// CHECK: checked by the cypher program
+    pub crank_authority: AccountInfo<'info>,
+    /// CHECK: checked by the cypher program
+    pub dex_program: AccountInfo<'info>,
+}
+
 pub fn init_open_orders<'info>(
     ctx: CpiContext<'_, '_, '_, 'info, InitOpenOrdersCpi<'info>>,
 ) -> Result<()> {
@@ -20,7 +42,7 @@ pub fn init_open_orders<'info>(
         ctx.accounts.cypher_group.key,
         ctx.accounts.cypher_user.key,
         ctx.accounts.payer.key,
-        ctx.accounts.user_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
         ctx.accounts.dex_market.key,
         ctx.accounts.open_orders.key,
         ctx.accounts.init_oo_authority.key,
@@ -39,7 +61,7 @@ pub fn close_open_orders<'info>(
     let ix = close_open_orders_ix(
         ctx.accounts.cypher_group.key,
         ctx.accounts.cypher_user.key,
-        ctx.accounts.user_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
         ctx.accounts.dex_market.key,
         ctx.accounts.open_orders.key,
     );
@@ -60,7 +82,7 @@ pub fn new_order_v3<'info>(
         ctx.accounts.vault_signer.key,
         ctx.accounts.price_history.key,
         ctx.accounts.cypher_user.key,
-        ctx.accounts.user_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
         ctx.accounts.c_asset_mint.key,
         ctx.accounts.cypher_c_asset_vault.key,
         ctx.accounts.cypher_pc_vault.key,
@@ -91,7 +113,7 @@ pub fn cancel_order_v2<'info>(
         ctx.accounts.cypher_group.key,
         ctx.accounts.vault_signer.key,
         ctx.accounts.cypher_user.key,
-        ctx.accounts.user_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
         ctx.accounts.c_asset_mint.key,
         ctx.accounts.cypher_c_asset_vault.key,
         ctx.accounts.cypher_pc_vault.key,
@@ -122,7 +144,7 @@ pub fn cancel_order_by_client_id_v2<'info>(
         ctx.accounts.cypher_group.key,
         ctx.accounts.vault_signer.key,
         ctx.accounts.cypher_user.key,
-        ctx.accounts.user_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
         ctx.accounts.c_asset_mint.key,
         ctx.accounts.cypher_c_asset_vault.key,
         ctx.accounts.cypher_pc_vault.key,
@@ -152,7 +174,7 @@ pub fn settle_funds<'info>(
         ctx.accounts.cypher_group.key,
         ctx.accounts.vault_signer.key,
         ctx.accounts.cypher_user.key,
-        ctx.accounts.user_signer.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
         ctx.accounts.c_asset_mint.key,
         ctx.accounts.cypher_c_asset_vault.key,
         ctx.accounts.cypher_pc_vault.key,
@@ -169,3 +191,65 @@ pub fn settle_funds<'info>(
     )?;
     Ok(())
 }
+
+pub fn prune<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, CancelOrderCpi<'info>>,
+    limit: u16,
+) -> Result<()> {
+    let ix = prune_ix(
+        ctx.accounts.cypher_group.key,
+        ctx.accounts.vault_signer.key,
+        ctx.accounts.cypher_user.key,
+        &UserSigner::unchecked(&ctx.accounts.user_signer),
+        ctx.accounts.c_asset_mint.key,
+        ctx.accounts.cypher_c_asset_vault.key,
+        ctx.accounts.cypher_pc_vault.key,
+        ctx.accounts.NoOpCancelOrderdex.market.key,
+        ctx.accounts.NoOpCancelOrderdex.prune_authority.key,
+        ctx.accounts.NoOpCancelOrderdex.open_orders.key,
+        ctx.accounts.NoOpCancelOrderdex.event_q.key,
+        ctx.accounts.NoOpCancelOrderdex.bids.key,
+        ctx.accounts.NoOpCancelOrderdex.asks.key,
+        ctx.accounts.NoOpCancelOrderdex.coin_vault.key,
+        ctx.accounts.NoOpCancelOrderdex.pc_vault.key,
+        ctx.accounts.NoOpCancelOrderdex.vault_signer.key,
+        limit,
+    );
+    invoke_signed(
+        &ix,
+        &ToAccountInfos::to_account_infos(&ctx),
+        ctx.signer_seeds,
+    )?;
+    Ok(())
+}
+
+/// the remaining accounts of the context must contain the cypher users followed by their open orders accounts,
+/// see [`ConsumeEvents`]
+pub fn consume_events<'info>(
+    ctx: CpiContext<'_, '_, '_, 'info, ConsumeEvents<'info>>,
+    limit: u16,
+) -> Result<()> {
+    let remaining_accounts = ctx.remaining_accounts.as_slice();
+    if remaining_accounts.len() % 2 != 0 {
+        return Err(ErrorCode::AccountNotEnoughKeys.into());
+    }
+    let (cypher_users, open_orders) = remaining_accounts.split_at(remaining_accounts.len() / 2);
+    let cypher_users: Vec<Pubkey> = cypher_users.iter().map(|a| *a.key).collect();
+    let open_orders: Vec<Pubkey> = open_orders.iter().map(|a| *a.key).collect();
+
+    let ix = consume_events_ix(
+        ctx.accounts.cypher_group.key,
+        &cypher_users,
+        &open_orders,
+        ctx.accounts.dex_market.key,
+        ctx.accounts.event_queue.key,
+        ctx.accounts.crank_authority.key,
+        limit,
+    );
+    invoke_signed(
+        &ix,
+        &ToAccountInfos::to_account_infos(&ctx),
+        ctx.signer_seeds,
+    )?;
+    Ok(())
+}
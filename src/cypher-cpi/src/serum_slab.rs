@@ -139,6 +139,11 @@ impl LeafNode {
     pub fn client_order_id(&self) -> u64 {
         self.client_order_id
     }
+
+    #[inline]
+    pub fn fee_tier(&self) -> Option<FeeTier> {
+        FeeTier::try_from(self.fee_tier).ok()
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -320,6 +325,17 @@ impl Slab {
         slab
     }
 
+    /// references the bytes as a read-only slab
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> &Self {
+        let len_without_header = bytes.len().checked_sub(SLAB_HEADER_LEN).unwrap();
+        let slop = len_without_header % size_of::<AnyNode>();
+        let bytes = &bytes[..bytes.len() - slop];
+        let slab: &Self = unsafe { &*(bytes as *const [u8] as *const Slab) };
+        slab.check_size_align();
+        slab
+    }
+
     // Each one of these does a preorder traversal
     pub fn get_depth(
         &self,
@@ -356,6 +372,12 @@ impl Slab {
         res
     }
 
+    /// gets every resting order in price-time priority, best first
+    pub fn get_orders(&self, is_asks: bool) -> Vec<&LeafNode> {
+        self.get_leaf_depth(self.header().leaf_count as usize, is_asks)
+            .unwrap_or_default()
+    }
+
     #[allow(clippy::ptr_offset_with_cast)]
     fn check_size_align(&self) {
         let (header_bytes, nodes_bytes) = array_refs![&self.0, SLAB_HEADER_LEN; .. ;];
@@ -450,3 +472,63 @@ impl Slab {
         Some(res)
     }
 }
+
+#[cfg(any(test, feature = "test-utils"))]
+impl Slab {
+    /// serializes the given orders as the bytes of a slab holding them in a crit-bit tree, the order ids must be unique
+    pub fn build_bytes(orders: &[LeafNode]) -> Vec<u8> {
+        let mut leaves = orders.to_vec();
+        leaves.sort_by_key(|leaf| leaf.order_id());
+        assert!(
+            leaves
+                .windows(2)
+                .all(|w| w[0].order_id() != w[1].order_id()),
+            "order ids must be unique"
+        );
+        let mut nodes = Vec::with_capacity(leaves.len() * 2);
+        let root_node = if leaves.is_empty() {
+            0
+        } else {
+            push_subtree(&leaves, &mut nodes)
+        };
+        let header = SlabHeader {
+            bump_index: nodes.len() as u64,
+            free_list_len: 0,
+            free_list_head: 0,
+            root_node,
+            leaf_count: leaves.len() as u64,
+        };
+        let mut bytes = bytemuck::bytes_of(&header).to_vec();
+        bytes.extend_from_slice(cast_slice(&nodes));
+        bytes
+    }
+}
+
+/// pushes the subtree of the given leaves, sorted by key, returning the handle of its root
+#[cfg(any(test, feature = "test-utils"))]
+fn push_subtree(leaves: &[LeafNode], nodes: &mut Vec<AnyNode>) -> NodeHandle {
+    let handle = nodes.len() as NodeHandle;
+    if let [leaf] = leaves {
+        nodes.push(*cast_ref(leaf));
+        return handle;
+    }
+    let key = leaves[0].order_id();
+    let prefix_len = (key ^ leaves[leaves.len() - 1].order_id()).leading_zeros();
+    let crit_bit_mask = (1u128 << 127) >> prefix_len;
+    let split = leaves
+        .iter()
+        .position(|leaf| leaf.order_id() & crit_bit_mask != 0)
+        .unwrap();
+    // the inner node is written once both children are pushed
+    nodes.push(AnyNode::zeroed());
+    let left = push_subtree(&leaves[..split], nodes);
+    let right = push_subtree(&leaves[split..], nodes);
+    nodes[handle as usize] = *cast_ref(&InnerNode {
+        tag: NodeTag::InnerNode.into(),
+        prefix_len,
+        key,
+        children: [left, right],
+        _padding: [0; 5],
+    });
+    handle
+}
@@ -0,0 +1,183 @@
+//! lifecycle helpers for expiring futures markets.
+//!
+//! once a market expires the admin executes it with `execute_market_ix`, after which every user with a position in
+//! the market settles it with `settle_position_ix` before the market can be closed with `close_market_ix`.
+use {
+    crate::{client::settle_position_ix, CypherGroup, CypherUser},
+    anchor_lang::{prelude::*, solana_program::instruction::Instruction},
+};
+
+/// a position in an executed market which has not been settled yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsettledPosition {
+    pub cypher_user: Pubkey,
+    pub market_idx: usize,
+    /// the base deposits of the c asset, in native units
+    pub deposits: u64,
+    /// the base borrows of the c asset, in native units
+    pub borrows: u64,
+    /// the expected settlement value at the market's oracle price in native quote units,
+    /// positive if the user is credited and negative if the user is debited
+    pub settlement_value: i128,
+}
+
+/// lists the users with an unsettled position in the given market, `None` if the market is not listed or not executed
+///
+/// open orders in the market should be settled beforehand, their balances are not included
+pub fn find_unsettled_positions<'a>(
+    group: &CypherGroup,
+    market_idx: usize,
+    users: impl IntoIterator<Item = (Pubkey, &'a CypherUser)>,
+) -> Option<Vec<UnsettledPosition>> {
+    let market = group.get_cypher_market(market_idx)?;
+    if !market.is_executed {
+        return None;
+    }
+    let oracle_price = market.oracle_price() as i128;
+    let positions = users
+        .into_iter()
+        .filter_map(|(cypher_user, user)| {
+            let position = user
+                .iter_positions()
+                .find(|p| p.market_idx as usize == market_idx)?;
+            let deposits = position.base_deposits().as_u64(0);
+            let borrows = position.base_borrows().as_u64(0);
+            if deposits == 0 && borrows == 0 {
+                return None;
+            }
+            Some(UnsettledPosition {
+                cypher_user,
+                market_idx,
+                deposits,
+                borrows,
+                settlement_value: (deposits as i128 - borrows as i128) * oracle_price,
+            })
+        })
+        .collect();
+    Some(positions)
+}
+
+/// builds the settle instructions for the given positions, in batches of at most `batch_size` instructions
+///
+/// every batch is meant to be sent as a single transaction, no batch is built if `batch_size` is zero
+pub fn settle_position_ixs(
+    cypher_group: &Pubkey,
+    c_asset_mint: &Pubkey,
+    positions: &[UnsettledPosition],
+    batch_size: usize,
+) -> Vec<Vec<Instruction>> {
+    if batch_size == 0 {
+        return Vec::new();
+    }
+    positions
+        .chunks(batch_size)
+        .map(|batch| {
+            batch
+                .iter()
+                .map(|p| settle_position_ix(cypher_group, &p.cypher_user, c_asset_mint))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{constants::QUOTE_TOKEN_IDX, test_utils::*},
+    };
+
+    #[test]
+    fn ignores_markets_which_are_not_executed() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let user = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(0, 1_000)
+            .build();
+        assert_eq!(
+            find_unsettled_positions(&group, 0, [(Pubkey::new_unique(), &*user)]),
+            None
+        );
+        assert_eq!(
+            find_unsettled_positions(&group, 2, std::iter::empty()),
+            None
+        );
+    }
+
+    #[test]
+    fn values_long_and_short_positions_at_oracle_price() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 50)
+            .market(1, Pubkey::new_unique(), 6, 50)
+            .oracle_price(0, 40)
+            .executed(0)
+            .build();
+        let owner = Pubkey::new_unique();
+        let long = CypherUserBuilder::new(group.self_address, owner)
+            .deposit(0, 1_000)
+            .build();
+        let short = CypherUserBuilder::new(group.self_address, owner)
+            .borrow(0, 300)
+            .deposit(1, 1_000)
+            .build();
+        let empty = CypherUserBuilder::new(group.self_address, owner)
+            .deposit(0, 0)
+            .deposit(QUOTE_TOKEN_IDX, 1_000)
+            .build();
+        let other_market = CypherUserBuilder::new(group.self_address, owner)
+            .deposit(1, 1_000)
+            .build();
+        let keys: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let positions = find_unsettled_positions(
+            &group,
+            0,
+            [
+                (keys[0], &*long),
+                (keys[1], &*short),
+                (keys[2], &*empty),
+                (keys[3], &*other_market),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            positions,
+            vec![
+                UnsettledPosition {
+                    cypher_user: keys[0],
+                    market_idx: 0,
+                    deposits: 1_000,
+                    borrows: 0,
+                    settlement_value: 40_000,
+                },
+                UnsettledPosition {
+                    cypher_user: keys[1],
+                    market_idx: 0,
+                    deposits: 0,
+                    borrows: 300,
+                    settlement_value: -12_000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn batches_settle_instructions() {
+        let positions: Vec<UnsettledPosition> = (0..5)
+            .map(|_| UnsettledPosition {
+                cypher_user: Pubkey::new_unique(),
+                market_idx: 0,
+                deposits: 1,
+                borrows: 0,
+                settlement_value: 40,
+            })
+            .collect();
+        let (group, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let batch_lens: Vec<usize> = settle_position_ixs(&group, &mint, &positions, 2)
+            .iter()
+            .map(|batch| batch.len())
+            .collect();
+        assert_eq!(batch_lens, vec![2, 2, 1]);
+        assert!(settle_position_ixs(&group, &mint, &positions, 0).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use {anchor_lang::prelude::*, std::ops::Deref};
+
+/// the key signing an instruction on behalf of a cypher user
+///
+/// the instruction builders in `client` take the `user_signer` as a `UserSigner`: the owner may sign any instruction,
+/// the delegate only those whose action does not require the owner, see [`UserAction::requires_owner`], and the
+/// builders of owner-only instructions reject a delegate with [`SignerError::OwnerRequired`]
+///
+/// a pda owner or delegate signs through `invoke_signed`, the builders flag its account as a signer even where the
+/// idl does not, see [`UserSigner::sign_account_metas`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSigner {
+    /// the cypher user's owner, stored as `CypherUser::user_signer`
+    Owner(Pubkey),
+    /// the cypher user's delegate, set with `set_delegate_ix`
+    Delegate(Pubkey),
+    /// the cypher user's owner, a pda signing with `invoke_signed`
+    OwnerPda(Pubkey),
+    /// the cypher user's delegate, a pda signing with `invoke_signed`
+    DelegatePda(Pubkey),
+}
+
+impl UserSigner {
+    /// wraps a signer whose role is left for the cypher program to check, used by the CPI wrappers which only get
+    /// the signer's account, an account which did not sign the transaction can only sign the CPI as a pda
+    pub(crate) fn unchecked(account: &AccountInfo) -> Self {
+        if account.is_signer {
+            UserSigner::Owner(*account.key)
+        } else {
+            UserSigner::OwnerPda(*account.key)
+        }
+    }
+
+    /// gets the signer's pubkey
+    pub fn pubkey(&self) -> Pubkey {
+        **self
+    }
+
+    /// checks whether the signer is the owner
+    pub fn is_owner(&self) -> bool {
+        matches!(self, UserSigner::Owner(_) | UserSigner::OwnerPda(_))
+    }
+
+    /// checks whether the signer is a pda
+    pub fn is_pda(&self) -> bool {
+        matches!(self, UserSigner::OwnerPda(_) | UserSigner::DelegatePda(_))
+    }
+
+    /// flags the signer's account metas as signers if it is a pda, otherwise they keep the flags of the idl
+    pub fn sign_account_metas(&self, mut metas: Vec<AccountMeta>) -> Vec<AccountMeta> {
+        if self.is_pda() {
+            for meta in metas.iter_mut().filter(|meta| meta.pubkey == **self) {
+                meta.is_signer = true;
+            }
+        }
+        metas
+    }
+
+    /// checks whether this signer may perform the given action
+    pub fn can_perform(&self, action: UserAction) -> bool {
+        self.is_owner() || !action.requires_owner()
+    }
+
+    /// gets the signer's pubkey if it may perform the given action
+    pub fn check(&self, action: UserAction) -> std::result::Result<Pubkey, SignerError> {
+        if self.can_perform(action) {
+            Ok(self.pubkey())
+        } else {
+            Err(SignerError::OwnerRequired(action))
+        }
+    }
+}
+
+/// an error building an instruction on behalf of a cypher user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignerError {
+    /// the action can only be performed by the cypher user's owner
+    OwnerRequired(UserAction),
+    /// the instruction is not in [`crate::ix_accounts::INSTRUCTIONS`]
+    UnknownInstruction,
+    /// the instruction, with the given name, has no account with the requested name
+    UnknownAccount(&'static str),
+}
+
+impl From<SignerError> for anchor_lang::error::Error {
+    fn from(err: SignerError) -> Self {
+        msg!("cypher signer error: {:?}", err);
+        match err {
+            SignerError::OwnerRequired(_) => ErrorCode::ConstraintSigner.into(),
+            SignerError::UnknownInstruction => ErrorCode::InstructionFallbackNotFound.into(),
+            SignerError::UnknownAccount(_) => ErrorCode::AccountNotEnoughKeys.into(),
+        }
+    }
+}
+
+impl Deref for UserSigner {
+    type Target = Pubkey;
+
+    fn deref(&self) -> &Pubkey {
+        match self {
+            UserSigner::Owner(pk)
+            | UserSigner::Delegate(pk)
+            | UserSigner::OwnerPda(pk)
+            | UserSigner::DelegatePda(pk) => pk,
+        }
+    }
+}
+
+/// the actions a signer can perform on a cypher user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserAction {
+    CloseAccount,
+    SetDelegate,
+    Deposit,
+    Withdraw,
+    Liquidate,
+    InitOpenOrders,
+    CloseOpenOrders,
+    NewOrder,
+    CancelOrder,
+    SettleFunds,
+}
+
+impl UserAction {
+    /// checks whether the action can only be performed by the owner
+    ///
+    /// anything that moves funds out of the account or changes who controls it needs the owner,
+    /// trading and managing open orders can be delegated
+    pub fn requires_owner(&self) -> bool {
+        matches!(
+            self,
+            UserAction::CloseAccount | UserAction::SetDelegate | UserAction::Withdraw
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::client::{close_cypher_user_ix, set_delegate_ix, withdraw_collateral_ix},
+        anchor_lang::solana_program::instruction::Instruction,
+    };
+
+    #[test]
+    fn owner_only_builders_reject_delegate() {
+        let k: Vec<Pubkey> = (0..6).map(|_| Pubkey::new_unique()).collect();
+        let build = |user_signer: &UserSigner| {
+            [
+                close_cypher_user_ix(&k[0], &k[1], user_signer).err(),
+                set_delegate_ix(&k[0], &k[1], user_signer, &k[2]).err(),
+                withdraw_collateral_ix(&k[0], &k[1], &k[2], &k[3], user_signer, &k[4], 100).err(),
+            ]
+        };
+
+        assert_eq!(build(&UserSigner::Owner(k[5])), [None, None, None]);
+        assert_eq!(
+            build(&UserSigner::Delegate(k[5])),
+            [
+                Some(SignerError::OwnerRequired(UserAction::CloseAccount)),
+                Some(SignerError::OwnerRequired(UserAction::SetDelegate)),
+                Some(SignerError::OwnerRequired(UserAction::Withdraw)),
+            ]
+        );
+    }
+
+    #[test]
+    fn builders_use_signer_pubkey() {
+        let k: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let ix = crate::client::deposit_collateral_ix(
+            &k[0],
+            &k[1],
+            &k[2],
+            &UserSigner::Delegate(k[3]),
+            &k[4],
+            100,
+        );
+        assert!(ix.accounts.iter().any(|a| a.pubkey == k[3] && a.is_signer));
+    }
+
+    #[test]
+    fn builders_flag_pda_signers() {
+        let k: Vec<Pubkey> = (0..11).map(|_| Pubkey::new_unique()).collect();
+        let build = |user_signer: &UserSigner| {
+            crate::client::settle_funds_ix(
+                &k[0],
+                &k[1],
+                &k[2],
+                user_signer,
+                &k[4],
+                &k[5],
+                &k[6],
+                &k[7],
+                &k[8],
+                &k[9],
+                &k[10],
+            )
+        };
+        let is_signer = |ix: &Instruction| {
+            ix.accounts
+                .iter()
+                .find(|a| a.pubkey == k[3])
+                .map(|a| a.is_signer)
+        };
+
+        assert_eq!(is_signer(&build(&UserSigner::Owner(k[3]))), Some(false));
+        assert_eq!(is_signer(&build(&UserSigner::Delegate(k[3]))), Some(false));
+        assert_eq!(is_signer(&build(&UserSigner::OwnerPda(k[3]))), Some(true));
+        assert_eq!(
+            is_signer(&build(&UserSigner::DelegatePda(k[3]))),
+            Some(true)
+        );
+
+        assert!(close_cypher_user_ix(&k[0], &k[1], &UserSigner::OwnerPda(k[3])).is_ok());
+        assert_eq!(
+            close_cypher_user_ix(&k[0], &k[1], &UserSigner::DelegatePda(k[3])).err(),
+            Some(SignerError::OwnerRequired(UserAction::CloseAccount))
+        );
+    }
+}
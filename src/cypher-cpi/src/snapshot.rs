@@ -0,0 +1,471 @@
+use {
+    crate::{serum_slab::Slab, utils::parse_dex_account, CypherGroup, CypherUser, PriceHistory},
+    anchor_lang::{
+        prelude::*, solana_program::system_instruction::MAX_PERMITTED_DATA_LENGTH, ZeroCopy,
+    },
+    bytemuck::{bytes_of_mut, from_bytes, Pod},
+    serum_dex::state::{Event, EventQueueHeader, EventView, MarketState, QueueHeader},
+    std::{
+        collections::BTreeMap,
+        convert::TryFrom,
+        fs::File,
+        io::{self, BufReader, BufWriter, Read, Write},
+        path::Path,
+    },
+};
+
+/// the magic bytes at the start of every snapshot file
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"CYSNAPSH";
+/// the current version of the snapshot format
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+/// the kind of an account in a snapshot, used to pick the decoder when reading it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum AccountKind {
+    CypherGroup = 0,
+    CypherUser = 1,
+    PriceHistory = 2,
+    DexMarket = 3,
+    Bids = 4,
+    Asks = 5,
+    EventQueue = 6,
+    Other = 255,
+}
+
+impl TryFrom<u8> for AccountKind {
+    type Error = u8;
+
+    /// converts a stored kind, returning it back if it is unknown
+    fn try_from(kind: u8) -> std::result::Result<Self, u8> {
+        Ok(match kind {
+            0 => AccountKind::CypherGroup,
+            1 => AccountKind::CypherUser,
+            2 => AccountKind::PriceHistory,
+            3 => AccountKind::DexMarket,
+            4 => AccountKind::Bids,
+            5 => AccountKind::Asks,
+            6 => AccountKind::EventQueue,
+            255 => AccountKind::Other,
+            _ => return Err(kind),
+        })
+    }
+}
+
+/// an account captured in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotAccount {
+    pub kind: AccountKind,
+    pub pubkey: Pubkey,
+    pub data: Vec<u8>,
+}
+
+/// a consistent set of accounts captured at a single slot
+///
+/// the file format is, with every integer in little endian:
+///
+/// - the magic bytes `CYSNAPSH`
+/// - the version as a `u16`
+/// - the slot as a `u64`
+/// - the number of accounts as a `u32`
+/// - for each account, its kind as a `u8`, its pubkey, the data length as a `u32` and the data
+///
+/// an account longer than `MAX_PERMITTED_DATA_LENGTH` is rejected when reading the file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub slot: u64,
+    accounts: BTreeMap<Pubkey, SnapshotAccount>,
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn get_zero_copy<T: ZeroCopy + Owner>(data: &[u8]) -> Option<&T> {
+    let end = std::mem::size_of::<T>() + 8;
+    if data.len() < end || data[..8] != T::discriminator() {
+        return None;
+    }
+    Some(from_bytes::<T>(&data[8..end]))
+}
+
+/// copies a pod value out of bytes which may not be aligned for it
+fn read_unaligned<T: Pod>(bytes: &[u8]) -> T {
+    let mut value = T::zeroed();
+    bytes_of_mut(&mut value).copy_from_slice(bytes);
+    value
+}
+
+impl Snapshot {
+    pub fn new(slot: u64) -> Self {
+        Self {
+            slot,
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    /// adds an account to the snapshot, replacing any account with the same pubkey
+    pub fn add_account(&mut self, kind: AccountKind, pubkey: Pubkey, data: Vec<u8>) {
+        self.accounts
+            .insert(pubkey, SnapshotAccount { kind, pubkey, data });
+    }
+
+    pub fn get_account(&self, pubkey: &Pubkey) -> Option<&SnapshotAccount> {
+        self.accounts.get(pubkey)
+    }
+
+    /// iterates over the accounts of the given kind, ordered by pubkey
+    pub fn iter_accounts(&self, kind: AccountKind) -> impl Iterator<Item = &SnapshotAccount> {
+        self.accounts.values().filter(move |a| a.kind == kind)
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.slot.to_le_bytes())?;
+        writer.write_all(&(self.accounts.len() as u32).to_le_bytes())?;
+        for account in self.accounts.values() {
+            writer.write_all(&[account.kind as u8])?;
+            writer.write_all(account.pubkey.as_ref())?;
+            writer.write_all(&(account.data.len() as u32).to_le_bytes())?;
+            writer.write_all(&account.data)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        if &read_array::<R, 8>(reader)? != SNAPSHOT_MAGIC {
+            return Err(invalid_data("not a cypher snapshot"));
+        }
+        let version = u16::from_le_bytes(read_array(reader)?);
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid_data("unsupported cypher snapshot version"));
+        }
+        let mut snapshot = Self::new(u64::from_le_bytes(read_array(reader)?));
+        let accounts_len = u32::from_le_bytes(read_array(reader)?);
+        for _ in 0..accounts_len {
+            let [kind] = read_array::<R, 1>(reader)?;
+            let pubkey = Pubkey::new_from_array(read_array(reader)?);
+            let data_len = u32::from_le_bytes(read_array(reader)?) as u64;
+            // the length is read from the file, so it is checked before allocating
+            if data_len > MAX_PERMITTED_DATA_LENGTH {
+                return Err(invalid_data("cypher snapshot account data is too long"));
+            }
+            let data_len = data_len as usize;
+            let mut data = vec![0u8; data_len];
+            reader.read_exact(&mut data)?;
+            let kind = AccountKind::try_from(kind)
+                .map_err(|_| invalid_data("unknown cypher snapshot account kind"))?;
+            snapshot.add_account(kind, pubkey, data);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    fn get_data(&self, pubkey: &Pubkey, kind: AccountKind) -> Option<&[u8]> {
+        self.accounts
+            .get(pubkey)
+            .filter(|a| a.kind == kind)
+            .map(|a| a.data.as_slice())
+    }
+
+    pub fn cypher_group(&self, pubkey: &Pubkey) -> Option<&CypherGroup> {
+        get_zero_copy(self.get_data(pubkey, AccountKind::CypherGroup)?)
+    }
+
+    pub fn cypher_user(&self, pubkey: &Pubkey) -> Option<&CypherUser> {
+        get_zero_copy(self.get_data(pubkey, AccountKind::CypherUser)?)
+    }
+
+    /// iterates over every cypher user in the snapshot with its pubkey
+    pub fn cypher_users(&self) -> impl Iterator<Item = (Pubkey, &CypherUser)> {
+        self.iter_accounts(AccountKind::CypherUser)
+            .filter_map(|a| get_zero_copy(&a.data).map(|user| (a.pubkey, user)))
+    }
+
+    pub fn price_history(&self, pubkey: &Pubkey) -> Option<&PriceHistory> {
+        get_zero_copy(self.get_data(pubkey, AccountKind::PriceHistory)?)
+    }
+
+    pub fn dex_market(&self, pubkey: &Pubkey) -> Option<MarketState> {
+        let data = self.get_data(pubkey, AccountKind::DexMarket)?;
+        if data.len() != std::mem::size_of::<MarketState>() + 12 {
+            return None;
+        }
+        Some(parse_dex_account(data))
+    }
+
+    /// gets the order book side stored in the given bids or asks account
+    pub fn slab(&self, pubkey: &Pubkey) -> Option<&Slab> {
+        let account = self
+            .accounts
+            .get(pubkey)
+            .filter(|a| a.kind == AccountKind::Bids || a.kind == AccountKind::Asks)?;
+        let len = account.data.len();
+        if len < 13 + 7 + 32 {
+            return None;
+        }
+        Some(Slab::from_bytes(&account.data[13..len - 7]))
+    }
+
+    /// gets the events of the given event queue, oldest first
+    pub fn event_queue(&self, pubkey: &Pubkey) -> Option<Vec<EventView>> {
+        let data = self.get_data(pubkey, AccountKind::EventQueue)?;
+        let header_len = std::mem::size_of::<EventQueueHeader>();
+        let event_len = std::mem::size_of::<Event>();
+        if data.len() < 5 + header_len + 7 {
+            return None;
+        }
+        let data = &data[5..data.len() - 7];
+        let header: EventQueueHeader = read_unaligned(&data[..header_len]);
+        let capacity = ((data.len() - header_len) / event_len) as u64;
+        if header.count() > capacity {
+            return None;
+        }
+        (0..header.count())
+            .map(|i| {
+                let start = header_len + ((header.head() + i) % capacity) as usize * event_len;
+                read_unaligned::<Event>(&data[start..start + event_len])
+                    .as_view()
+                    .ok()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::test_utils::{to_account_data, CypherGroupBuilder, CypherUserBuilder, SlabBuilder},
+        bytemuck::bytes_of,
+        serum_dex::matching::Side,
+        std::num::NonZeroU64,
+    };
+
+    /// wraps the slab in serum's padding, after the account flags
+    fn slab_account_data(slab: &[u8]) -> Vec<u8> {
+        let mut bytes = b"serum".to_vec();
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(slab);
+        bytes.extend_from_slice(b"padding");
+        bytes
+    }
+
+    fn out_event(order_id: u128, client_order_id: u64) -> Event {
+        Event::new(EventView::Out {
+            side: Side::Bid,
+            release_funds: false,
+            native_qty_unlocked: 100,
+            native_qty_still_locked: 0,
+            order_id,
+            owner: [0; 4],
+            owner_slot: 0,
+            client_order_id: NonZeroU64::new(client_order_id),
+        })
+    }
+
+    fn snapshot() -> Snapshot {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let user = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(0, 1_000_000)
+            .build();
+        let mut snapshot = Snapshot::new(42);
+        snapshot.add_account(
+            AccountKind::CypherGroup,
+            group.self_address,
+            to_account_data(group.as_ref()),
+        );
+        snapshot.add_account(
+            AccountKind::CypherUser,
+            Pubkey::new_unique(),
+            to_account_data(user.as_ref()),
+        );
+        snapshot.add_account(AccountKind::Other, Pubkey::new_unique(), vec![1, 2, 3]);
+        snapshot
+    }
+
+    fn to_bytes(snapshot: &Snapshot) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = snapshot();
+        let read = Snapshot::read_from(&mut to_bytes(&snapshot).as_slice()).unwrap();
+        assert_eq!(read, snapshot);
+        assert_eq!(read.slot, 42);
+        assert_eq!(read.cypher_users().count(), 1);
+        let group_pk = read
+            .iter_accounts(AccountKind::CypherGroup)
+            .next()
+            .unwrap()
+            .pubkey;
+        assert!(read.cypher_group(&group_pk).is_some());
+        assert!(read.cypher_user(&group_pk).is_none());
+    }
+
+    #[test]
+    fn rejects_version_mismatch() {
+        let mut bytes = to_bytes(&snapshot());
+        bytes[8..10].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        let err = Snapshot::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "unsupported cypher snapshot version");
+    }
+
+    #[test]
+    fn rejects_unknown_account_kind() {
+        let mut snapshot = Snapshot::new(1);
+        snapshot.add_account(AccountKind::Other, Pubkey::new_unique(), vec![]);
+        let mut bytes = to_bytes(&snapshot);
+        // the first account's kind follows the magic, version, slot and account count
+        bytes[8 + 2 + 8 + 4] = 7;
+        let err = Snapshot::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "unknown cypher snapshot account kind");
+        assert_eq!(AccountKind::try_from(7), Err(7));
+        assert_eq!(AccountKind::try_from(255), Ok(AccountKind::Other));
+    }
+
+    #[test]
+    fn rejects_oversized_account_data() {
+        let mut snapshot = Snapshot::new(1);
+        snapshot.add_account(AccountKind::Other, Pubkey::new_unique(), vec![]);
+        let mut bytes = to_bytes(&snapshot);
+        // the first account's data length follows its kind and pubkey
+        let len_offset = 8 + 2 + 8 + 4 + 1 + 32;
+        bytes[len_offset..len_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = Snapshot::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.to_string(), "cypher snapshot account data is too long");
+    }
+
+    #[test]
+    fn decodes_dex_markets() {
+        let address = Pubkey::new_unique();
+        let mut market: MarketState = unsafe { std::mem::zeroed() };
+        market.own_address = bytemuck::cast(address.to_bytes());
+        market.coin_lot_size = 100_000;
+        market.pc_lot_size = 10;
+        // the market's account flags are part of `MarketState`, so only the padding is added around it
+        let mut data = b"serum".to_vec();
+        data.extend_from_slice(bytes_of(&market));
+        data.extend_from_slice(b"padding");
+
+        let mut snapshot = Snapshot::new(1);
+        snapshot.add_account(AccountKind::DexMarket, address, data.clone());
+        let read = Snapshot::read_from(&mut to_bytes(&snapshot).as_slice()).unwrap();
+        let decoded = read.dex_market(&address).unwrap();
+        assert_eq!(decoded.own_address, market.own_address);
+        assert_eq!({ decoded.coin_lot_size }, 100_000);
+        assert_eq!({ decoded.pc_lot_size }, 10);
+
+        snapshot.add_account(
+            AccountKind::DexMarket,
+            address,
+            data[..data.len() - 1].to_vec(),
+        );
+        assert!(snapshot.dex_market(&address).is_none());
+    }
+
+    #[test]
+    fn decodes_slabs() {
+        let (bids, asks, owner) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut snapshot = Snapshot::new(1);
+        snapshot.add_account(
+            AccountKind::Bids,
+            bids,
+            slab_account_data(
+                &SlabBuilder::bids()
+                    .order(1, 100, 5, &owner)
+                    .order(2, 101, 7, &owner)
+                    .build(),
+            ),
+        );
+        snapshot.add_account(
+            AccountKind::Asks,
+            asks,
+            slab_account_data(&SlabBuilder::asks().order(3, 102, 9, &owner).build()),
+        );
+        let read = Snapshot::read_from(&mut to_bytes(&snapshot).as_slice()).unwrap();
+
+        let orders = read.slab(&bids).unwrap().get_orders(false);
+        assert_eq!(
+            orders
+                .iter()
+                .map(|o| (o.price().get(), o.quantity(), o.client_order_id()))
+                .collect::<Vec<_>>(),
+            vec![(101, 7, 2), (100, 5, 1)]
+        );
+        let orders = read.slab(&asks).unwrap().get_orders(true);
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].quantity(), 9);
+
+        snapshot.add_account(AccountKind::Other, bids, vec![0; 100]);
+        assert!(snapshot.slab(&bids).is_none());
+        snapshot.add_account(AccountKind::Bids, bids, vec![0; 13 + 7]);
+        assert!(snapshot.slab(&bids).is_none());
+    }
+
+    #[test]
+    fn decodes_event_queues_oldest_first() {
+        // a queue of three events whose two events wrap around its end
+        let mut data = Vec::new();
+        for field in [0u64, 2, 2, 5] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        for event in [out_event(1, 11), out_event(0, 0), out_event(3, 13)] {
+            data.extend_from_slice(bytes_of(&event));
+        }
+        let mut bytes = b"serum".to_vec();
+        bytes.extend_from_slice(&data);
+        bytes.extend_from_slice(b"padding");
+
+        let address = Pubkey::new_unique();
+        let mut snapshot = Snapshot::new(1);
+        snapshot.add_account(AccountKind::EventQueue, address, bytes.clone());
+        let read = Snapshot::read_from(&mut to_bytes(&snapshot).as_slice()).unwrap();
+        let events = read.event_queue(&address).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| match event {
+                    EventView::Out {
+                        order_id,
+                        client_order_id,
+                        ..
+                    } => (*order_id, client_order_id.map(NonZeroU64::get)),
+                    EventView::Fill { .. } => panic!("unexpected fill"),
+                })
+                .collect::<Vec<_>>(),
+            vec![(3, Some(13)), (1, Some(11))]
+        );
+
+        // a count larger than the capacity of the queue
+        bytes[5 + 16..5 + 24].copy_from_slice(&4u64.to_le_bytes());
+        snapshot.add_account(AccountKind::EventQueue, address, bytes);
+        assert!(snapshot.event_queue(&address).is_none());
+    }
+}
@@ -0,0 +1,169 @@
+use {
+    crate::utils::{derive_cypher_user_address, derive_cypher_user_address_with_number},
+    anchor_lang::prelude::*,
+    std::{collections::HashMap, ops::Range},
+};
+
+/// a cypher user address of an owner in a cypher group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubAccount {
+    /// the account number used by `create_cypher_user_ix`, `None` for the legacy `init_cypher_user_ix` account
+    pub account_number: Option<u64>,
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+impl SubAccount {
+    /// derives the legacy cypher user account, created with `init_cypher_user_ix`
+    pub fn legacy(cypher_group_pk: &Pubkey, owner_pk: &Pubkey) -> Self {
+        let (address, bump) = derive_cypher_user_address(cypher_group_pk, owner_pk);
+        Self {
+            account_number: None,
+            address,
+            bump,
+        }
+    }
+
+    /// derives the cypher user account with the given account number, created with `create_cypher_user_ix`
+    pub fn numbered(cypher_group_pk: &Pubkey, owner_pk: &Pubkey, account_number: u64) -> Self {
+        let (address, bump) =
+            derive_cypher_user_address_with_number(cypher_group_pk, owner_pk, account_number);
+        Self {
+            account_number: Some(account_number),
+            address,
+            bump,
+        }
+    }
+
+    /// checks whether this is the legacy cypher user account
+    pub fn is_legacy(&self) -> bool {
+        self.account_number.is_none()
+    }
+}
+
+/// derives the cypher user accounts for every account number in the given range
+pub fn derive_sub_accounts(
+    cypher_group_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    account_numbers: Range<u64>,
+) -> Vec<SubAccount> {
+    account_numbers
+        .map(|n| SubAccount::numbered(cypher_group_pk, owner_pk, n))
+        .collect()
+}
+
+/// finds the owner's existing cypher user accounts in the given account map
+///
+/// the legacy account comes first if it exists, followed by the numbered accounts in the given range
+pub fn find_sub_accounts<'a, T>(
+    cypher_group_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    account_numbers: Range<u64>,
+    accounts: &'a HashMap<Pubkey, T>,
+) -> Vec<(SubAccount, &'a T)> {
+    std::iter::once(SubAccount::legacy(cypher_group_pk, owner_pk))
+        .chain(derive_sub_accounts(
+            cypher_group_pk,
+            owner_pk,
+            account_numbers,
+        ))
+        .filter_map(|sub_account| {
+            accounts
+                .get(&sub_account.address)
+                .map(|account| (sub_account, account))
+        })
+        .collect()
+}
+
+/// gets the lowest account number whose cypher user account is not in the given account map
+pub fn next_free_account_number<T>(
+    cypher_group_pk: &Pubkey,
+    owner_pk: &Pubkey,
+    accounts: &HashMap<Pubkey, T>,
+) -> u64 {
+    (0..)
+        .find(|n| {
+            let sub_account = SubAccount::numbered(cypher_group_pk, owner_pk, *n);
+            !accounts.contains_key(&sub_account.address)
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> (Pubkey, Pubkey) {
+        (Pubkey::new_unique(), Pubkey::new_unique())
+    }
+
+    #[test]
+    fn derives_addresses_from_cypher_user_seeds() {
+        let (group, owner) = keys();
+        let (address, bump) = Pubkey::find_program_address(
+            &[b"cypher_user", group.as_ref(), owner.as_ref()],
+            &crate::id(),
+        );
+        let legacy = SubAccount::legacy(&group, &owner);
+        assert_eq!((legacy.address, legacy.bump), (address, bump));
+        assert!(legacy.is_legacy());
+
+        let sub_accounts = derive_sub_accounts(&group, &owner, 0..3);
+        assert_eq!(sub_accounts.len(), 3);
+        for (n, sub_account) in sub_accounts.iter().enumerate() {
+            let (address, bump) = Pubkey::find_program_address(
+                &[
+                    b"cypher_user",
+                    group.as_ref(),
+                    owner.as_ref(),
+                    &(n as u64).to_le_bytes(),
+                ],
+                &crate::id(),
+            );
+            assert_eq!(sub_account.account_number, Some(n as u64));
+            assert_eq!((sub_account.address, sub_account.bump), (address, bump));
+            assert!(!sub_account.is_legacy());
+        }
+        assert_ne!(sub_accounts[0].address, legacy.address);
+    }
+
+    #[test]
+    fn finds_accounts_around_a_gap() {
+        let (group, owner) = keys();
+        let accounts: HashMap<Pubkey, u64> = [0, 1, 3]
+            .iter()
+            .map(|n| (SubAccount::numbered(&group, &owner, *n).address, *n))
+            .chain(std::iter::once((
+                SubAccount::legacy(&group, &owner).address,
+                u64::MAX,
+            )))
+            .collect();
+
+        let found = find_sub_accounts(&group, &owner, 0..5, &accounts);
+        let numbers: Vec<Option<u64>> = found.iter().map(|(s, _)| s.account_number).collect();
+        assert_eq!(numbers, [None, Some(0), Some(1), Some(3)]);
+        assert!(found
+            .iter()
+            .all(|(s, n)| s.account_number.unwrap_or(u64::MAX) == **n));
+        assert_eq!(next_free_account_number(&group, &owner, &accounts), 2);
+
+        // accounts outside of the range are not found
+        let found = find_sub_accounts(&group, &owner, 0..2, &accounts);
+        assert_eq!(found.len(), 3);
+    }
+
+    #[test]
+    fn next_free_account_after_all_used() {
+        let (group, owner) = keys();
+        let accounts: HashMap<Pubkey, ()> = derive_sub_accounts(&group, &owner, 0..4)
+            .into_iter()
+            .map(|s| (s.address, ()))
+            .collect();
+        assert_eq!(find_sub_accounts(&group, &owner, 0..4, &accounts).len(), 4);
+        assert_eq!(next_free_account_number(&group, &owner, &accounts), 4);
+
+        let other_owner = Pubkey::new_unique();
+        assert_eq!(next_free_account_number(&group, &other_owner, &accounts), 0);
+        assert!(find_sub_accounts(&group, &other_owner, 0..4, &accounts).is_empty());
+    }
+}
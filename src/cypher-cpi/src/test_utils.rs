@@ -0,0 +1,376 @@
+//! builders for in-memory cypher accounts, meant to unit test decoders and margin functions without a validator.
+//!
+//! ```ignore
+//! let group = CypherGroupBuilder::new()
+//!     .market(0, sol_mint, 6, 40)
+//!     .build();
+//! let user = CypherUserBuilder::new(group.self_address, owner)
+//!     .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+//!     .borrow(0, 2_000_000)
+//!     .build();
+//! let data = to_account_data(&*user);
+//! ```
+use {
+    crate::{
+        constants::*,
+        quote_mint,
+        serum_slab::{FeeTier, LeafNode, Slab},
+        CypherGroup, CypherUser, MarketType, OpenOrdersInfo, PriceHistory, PriceWithTs,
+    },
+    anchor_lang::{prelude::*, ZeroCopy},
+    bytemuck::{bytes_of, cast, Zeroable},
+    jet_proto_math::Number,
+};
+
+/// serializes a zero copy account into account data, prefixed with its discriminator
+pub fn to_account_data<T: ZeroCopy + Owner>(account: &T) -> Vec<u8> {
+    let mut data = T::discriminator().to_vec();
+    data.extend_from_slice(bytes_of(account));
+    data
+}
+
+/// builds a `CypherGroup` with sensible defaults, only the quote token is listed
+pub struct CypherGroupBuilder {
+    group: Box<CypherGroup>,
+}
+
+impl Default for CypherGroupBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CypherGroupBuilder {
+    pub fn new() -> Self {
+        let mut group = Box::new(CypherGroup::zeroed());
+        group.self_address = Pubkey::new_unique();
+        group.admin = Pubkey::new_unique();
+        group.vault_signer = Pubkey::new_unique();
+        group.config.margin_init_ratio = 150;
+        group.config.margin_maint_ratio = 110;
+        group.config.margin_partial_ratio = 130;
+        group.config.liquidator_bonus_bps = 500;
+        group.config.liquidation_insurance_fee_bps = 100;
+        group.config.maker_fee_bps = 0;
+        group.config.taker_fee_bps = 10;
+        Self { group }.quote_token(6)
+    }
+
+    /// sets the group's address
+    pub fn address(mut self, address: Pubkey) -> Self {
+        self.group.self_address = address;
+        self
+    }
+
+    /// sets the margin ratios, in percent
+    pub fn margin_ratios(mut self, init: u16, maint: u16, partial: u16) -> Self {
+        self.group.config.margin_init_ratio = init;
+        self.group.config.margin_maint_ratio = maint;
+        self.group.config.margin_partial_ratio = partial;
+        self
+    }
+
+    /// sets the maker and taker fees, in basis points
+    pub fn fees(mut self, maker_fee_bps: u16, taker_fee_bps: u16) -> Self {
+        self.group.config.maker_fee_bps = maker_fee_bps;
+        self.group.config.taker_fee_bps = taker_fee_bps;
+        self
+    }
+
+    /// sets the liquidation fees, in basis points
+    pub fn liquidation_fees(mut self, liquidator_bonus_bps: u16, insurance_fee_bps: u16) -> Self {
+        self.group.config.liquidator_bonus_bps = liquidator_bonus_bps;
+        self.group.config.liquidation_insurance_fee_bps = insurance_fee_bps;
+        self
+    }
+
+    /// sets the insurance fund and the accrued protocol fee
+    pub fn fund(mut self, insurance_fund: u64, protocol_fee: u64) -> Self {
+        self.group.insurance_fund = insurance_fund;
+        self.group.protocol_fee = protocol_fee;
+        self
+    }
+
+    fn token(mut self, token_idx: usize, mint: Pubkey, decimals: u8) -> Self {
+        let token = &mut self.group.tokens[token_idx];
+        token.mint = mint;
+        token.vault = Pubkey::new_unique();
+        token.deposit_index = Number::ONE.into_bytes();
+        token.borrow_index = Number::ONE.into_bytes();
+        token.config.decimals = decimals;
+        self
+    }
+
+    /// lists the quote token with the given decimals
+    pub fn quote_token(self, decimals: u8) -> Self {
+        self.token(QUOTE_TOKEN_IDX, quote_mint::ID, decimals)
+    }
+
+    /// sets the deposit and borrow indices of the given token
+    pub fn token_indices(
+        mut self,
+        token_idx: usize,
+        deposit_index: Number,
+        borrow_index: Number,
+    ) -> Self {
+        let token = &mut self.group.tokens[token_idx];
+        token.deposit_index = deposit_index.into_bytes();
+        token.borrow_index = borrow_index.into_bytes();
+        self
+    }
+
+    /// sets the base deposits and borrows of the given token
+    pub fn token_balances(
+        mut self,
+        token_idx: usize,
+        base_deposits: Number,
+        base_borrows: Number,
+    ) -> Self {
+        let token = &mut self.group.tokens[token_idx];
+        token.base_deposits = base_deposits.into_bytes();
+        token.base_borrows = base_borrows.into_bytes();
+        self
+    }
+
+    /// lists a pair future market and its c asset at the given index
+    ///
+    /// the market and oracle prices are in native quote per native c asset
+    pub fn market(
+        mut self,
+        market_idx: usize,
+        c_asset_mint: Pubkey,
+        decimals: u8,
+        price: u64,
+    ) -> Self {
+        assert!(market_idx < MARKETS_MAX_CNT);
+        self = self.token(market_idx, c_asset_mint, decimals);
+        let market = &mut self.group.markets[market_idx];
+        market.dex_market = Pubkey::new_unique();
+        market.price_history = Pubkey::new_unique();
+        market.pyth_products = Pubkey::new_unique();
+        market.market_price = price;
+        market.oracle_price.price = price;
+        market.market_type = MarketType::PairFuture;
+        self
+    }
+
+    /// sets the market price of the given market
+    pub fn market_price(mut self, market_idx: usize, price: u64) -> Self {
+        self.group.markets[market_idx].market_price = price;
+        self
+    }
+
+    /// sets the cached oracle price of the given market
+    pub fn oracle_price(mut self, market_idx: usize, price: u64) -> Self {
+        self.group.markets[market_idx].oracle_price.price = price;
+        self
+    }
+
+    /// sets the dex market of the given market
+    pub fn dex_market(mut self, market_idx: usize, dex_market: Pubkey) -> Self {
+        self.group.markets[market_idx].dex_market = dex_market;
+        self
+    }
+
+    /// sets the type and expiry of the given market
+    pub fn expiry(mut self, market_idx: usize, market_type: MarketType, expires_at: u64) -> Self {
+        let market = &mut self.group.markets[market_idx];
+        market.market_type = market_type;
+        market.expires_at = expires_at;
+        self
+    }
+
+    /// marks the given market as executed
+    pub fn executed(mut self, market_idx: usize) -> Self {
+        self.group.markets[market_idx].is_executed = true;
+        self
+    }
+
+    pub fn build(self) -> Box<CypherGroup> {
+        self.group
+    }
+}
+
+/// builds a `CypherUser` with no positions
+pub struct CypherUserBuilder {
+    user: Box<CypherUser>,
+}
+
+impl CypherUserBuilder {
+    pub fn new(cypher_group: Pubkey, owner: Pubkey) -> Self {
+        let mut user = Box::new(CypherUser::zeroed());
+        user.cypher_group = cypher_group;
+        user.user_signer = owner;
+        for position in user.positions.iter_mut() {
+            position.market_idx = u8::MAX;
+        }
+        user.positions[QUOTE_TOKEN_IDX].market_idx = QUOTE_TOKEN_IDX as u8;
+        Self { user }
+    }
+
+    pub fn delegate(mut self, delegate: Pubkey) -> Self {
+        self.user.delegate = delegate;
+        self
+    }
+
+    fn position_idx(&mut self, token_idx: usize) -> usize {
+        if token_idx == QUOTE_TOKEN_IDX {
+            return QUOTE_TOKEN_IDX;
+        }
+        if let Some(idx) = self.user.positions[..QUOTE_TOKEN_IDX]
+            .iter()
+            .position(|p| p.market_idx as usize == token_idx)
+        {
+            return idx;
+        }
+        let idx = self.user.positions[..QUOTE_TOKEN_IDX]
+            .iter()
+            .position(|p| p.market_idx as usize >= MARKETS_MAX_CNT)
+            .expect("no free position left");
+        self.user.positions[idx].market_idx = token_idx as u8;
+        idx
+    }
+
+    /// sets the base deposits of the given token, in native units
+    pub fn deposit(mut self, token_idx: usize, amount: u64) -> Self {
+        let idx = self.position_idx(token_idx);
+        self.user.positions[idx].base_deposits = Number::from(amount).into_bytes();
+        self
+    }
+
+    /// sets the base borrows of the given token, in native units
+    pub fn borrow(mut self, token_idx: usize, amount: u64) -> Self {
+        let idx = self.position_idx(token_idx);
+        self.user.positions[idx].base_borrows = Number::from(amount).into_bytes();
+        self
+    }
+
+    /// opens the open orders account of the given market with the given totals, in native units
+    pub fn open_orders(mut self, market_idx: usize, coin_total: u64, pc_total: u64) -> Self {
+        let idx = self.position_idx(market_idx);
+        let mut oo_info = OpenOrdersInfo::zeroed();
+        oo_info.coin_total = coin_total;
+        oo_info.coin_free = coin_total;
+        oo_info.pc_total = pc_total;
+        oo_info.pc_free = pc_total;
+        oo_info.is_account_open = true;
+        self.user.positions[idx].oo_info = oo_info;
+        self
+    }
+
+    pub fn build(self) -> Box<CypherUser> {
+        self.user
+    }
+}
+
+/// builds a `PriceHistory` for the given c asset with no recorded prices
+pub fn price_history(cypher_group: Pubkey, c_asset_mint: Pubkey) -> Box<PriceHistory> {
+    let mut price_history = Box::new(PriceHistory::zeroed());
+    price_history.cypher_group = cypher_group;
+    price_history.c_asset_mint = c_asset_mint;
+    price_history
+}
+
+/// writes a price at the given index of the price history's buffer and makes it the tail, the most recent price
+pub fn record_price(price_history: &mut PriceHistory, idx: usize, price: u64, timestamp: u64) {
+    assert!(idx < PRICE_HISTORY_SIZE);
+    let data = std::ptr::addr_of_mut!(price_history.data) as *mut PriceWithTs;
+    unsafe {
+        data.add(idx)
+            .write_unaligned(PriceWithTs { price, timestamp })
+    };
+    price_history.tail = idx as u16;
+}
+
+/// gets the serum order id of an order, bids sort their sequence numbers in reverse so older orders come first
+pub fn order_id(price_lots: u64, seq_num: u64, is_asks: bool) -> u128 {
+    let seq_num = if is_asks { seq_num } else { !seq_num };
+    ((price_lots as u128) << 64) | seq_num as u128
+}
+
+/// builds the bytes of a bids or asks `Slab`, which can be read back with `Slab::from_bytes`
+pub struct SlabBuilder {
+    is_asks: bool,
+    orders: Vec<LeafNode>,
+}
+
+impl SlabBuilder {
+    pub fn bids() -> Self {
+        Self {
+            is_asks: false,
+            orders: Vec::new(),
+        }
+    }
+
+    pub fn asks() -> Self {
+        Self {
+            is_asks: true,
+            orders: Vec::new(),
+        }
+    }
+
+    /// adds an order in lots, its order id is [`order_id`] and its client order id is `seq_num`
+    pub fn order(
+        mut self,
+        seq_num: u64,
+        price_lots: u64,
+        quantity_lots: u64,
+        owner: &Pubkey,
+    ) -> Self {
+        self.orders.push(LeafNode::new(
+            0,
+            order_id(price_lots, seq_num, self.is_asks),
+            cast(owner.to_bytes()),
+            quantity_lots,
+            FeeTier::Base,
+            seq_num,
+        ));
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        Slab::build_bytes(&self.orders)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::utils::get_zero_copy_account_ref};
+
+    #[test]
+    fn account_data_round_trips_through_decoder() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let data = to_account_data(&*group);
+        let decoded = get_zero_copy_account_ref::<CypherGroup>(&data).unwrap();
+        assert!(decoded
+            .get_cypher_token(QUOTE_TOKEN_IDX)
+            .unwrap()
+            .is_quote());
+        assert_eq!(decoded.get_cypher_market(0).unwrap().market_price, 40);
+        assert!(decoded.get_cypher_market(1).is_none());
+    }
+
+    #[test]
+    fn user_positions_are_valued_at_market_price() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let user = CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+            .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+            .borrow(0, 2_000_000)
+            .build();
+        let data = to_account_data(&*user);
+        let decoded = get_zero_copy_account_ref::<CypherUser>(&data).unwrap();
+        assert_eq!(decoded.iter_positions().count(), 1);
+        assert_eq!(
+            decoded.get_assets_value(&group),
+            Ok(Number::from(100_000_000_u64))
+        );
+        assert_eq!(
+            decoded.get_liabilities_value(&group),
+            Ok(Number::from(80_000_000_u64))
+        );
+    }
+}
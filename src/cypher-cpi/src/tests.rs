@@ -0,0 +1,238 @@
+use {
+    crate::{constants::*, test_utils::*, CypherGroup, MarginError},
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+    proptest::prelude::*,
+};
+
+const SOL_IDX: usize = 0;
+
+fn group_with_market(price: u64) -> Box<CypherGroup> {
+    CypherGroupBuilder::new()
+        .market(SOL_IDX, Pubkey::new_unique(), 6, price)
+        .build()
+}
+
+fn user_builder(group: &CypherGroup) -> CypherUserBuilder {
+    CypherUserBuilder::new(group.self_address, Pubkey::new_unique())
+}
+
+#[test]
+fn golden_quote_deposits_against_c_asset_borrows() {
+    let group = group_with_market(40);
+    let user = user_builder(&group)
+        .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+        .borrow(SOL_IDX, 2_000_000)
+        .build();
+    assert_eq!(
+        user.get_margin_c_ratio_components(&group),
+        Ok((
+            Number::from_percent(125_u16),
+            Number::from(100_000_000_u64),
+            Number::from(80_000_000_u64)
+        ))
+    );
+    assert_eq!(user.is_bankrupt(&group), Ok(false));
+}
+
+#[test]
+fn golden_open_orders_count_as_assets() {
+    let group = group_with_market(40);
+    let user = user_builder(&group)
+        .borrow(QUOTE_TOKEN_IDX, 100_000_000)
+        .open_orders(SOL_IDX, 1_000_000, 5_000_000)
+        .build();
+    assert_eq!(
+        user.get_assets_value(&group),
+        Ok(Number::from(45_000_000_u64))
+    );
+    assert_eq!(
+        user.get_margin_c_ratio(&group),
+        Ok(Number::from_percent(45_u16))
+    );
+}
+
+#[test]
+fn golden_quote_position_uses_token_indices() {
+    let group = CypherGroupBuilder::new()
+        .token_indices(
+            QUOTE_TOKEN_IDX,
+            Number::from_percent(110_u16),
+            Number::from_percent(120_u16),
+        )
+        .build();
+    let user = user_builder(&group)
+        .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+        .borrow(QUOTE_TOKEN_IDX, 50_000_000)
+        .build();
+    assert_eq!(
+        user.get_assets_value(&group),
+        Ok(Number::from(110_000_000_u64))
+    );
+    assert_eq!(
+        user.get_liabilities_value(&group),
+        Ok(Number::from(60_000_000_u64))
+    );
+}
+
+#[test]
+fn golden_bankruptcy_threshold() {
+    // the liquidation fee is 105% + 1%, covering one native unit at 40 takes 43 native quote units
+    let group = group_with_market(40);
+    let bankrupt = user_builder(&group)
+        .deposit(QUOTE_TOKEN_IDX, 42)
+        .borrow(SOL_IDX, 10)
+        .build();
+    let solvent = user_builder(&group)
+        .deposit(QUOTE_TOKEN_IDX, 43)
+        .borrow(SOL_IDX, 10)
+        .build();
+    assert_eq!(bankrupt.is_bankrupt(&group), Ok(true));
+    assert_eq!(solvent.is_bankrupt(&group), Ok(false));
+}
+
+#[test]
+fn bankruptcy_compares_values_larger_than_u64() {
+    let group = group_with_market(u64::MAX);
+    let values = crate::MarginValues {
+        assets_value: Number::from(u64::MAX) * u64::MAX,
+        liabilities_value: Number::from(u64::MAX),
+        largest_deposit_value: Number::from(u64::MAX) * u64::MAX,
+        lowest_borrow_price: Some(u64::MAX),
+    };
+    assert!(!values.is_bankrupt(&group));
+    let values = crate::MarginValues {
+        largest_deposit_value: Number::from(u64::MAX),
+        ..values
+    };
+    assert!(values.is_bankrupt(&group));
+}
+
+#[test]
+fn zero_liabilities_ratio_is_max() {
+    let group = group_with_market(40);
+    let empty = user_builder(&group).build();
+    assert_eq!(empty.get_margin_c_ratio(&group), Ok(Number::MAX));
+    assert_eq!(
+        empty.get_margin_c_ratio_components(&group),
+        Ok((Number::MAX, Number::ZERO, Number::ZERO))
+    );
+    assert_eq!(empty.is_bankrupt(&group), Ok(false));
+}
+
+#[test]
+fn missing_quote_token_is_an_error() {
+    let mut group = group_with_market(40);
+    group.tokens[QUOTE_TOKEN_IDX].mint = Pubkey::default();
+    let user = user_builder(&group).borrow(SOL_IDX, 1).build();
+    let err = Err(MarginError::QuoteTokenNotListed);
+    assert_eq!(user.get_assets_value(&group), err);
+    assert_eq!(user.get_liabilities_value(&group), err);
+    assert_eq!(user.get_margin_c_ratio(&group), err);
+    assert_eq!(user.is_bankrupt(&group), err);
+}
+
+#[test]
+fn missing_quote_position_is_valued_as_zero() {
+    let group = group_with_market(40);
+    let user = user_builder(&group).deposit(SOL_IDX, 1_000).build();
+    let values = user.get_margin_values(&group).unwrap();
+    assert_eq!(Ok(values.assets_value), user.get_assets_value(&group));
+    assert_eq!(values.liabilities_value, Number::ZERO);
+    assert_eq!(values.largest_deposit_value, Number::from(40_000_u64));
+    assert_eq!(values.lowest_borrow_price, None);
+}
+
+#[test]
+fn delisted_market_is_an_error() {
+    let group = group_with_market(40);
+    let user = user_builder(&group)
+        .deposit(QUOTE_TOKEN_IDX, 100)
+        .borrow(1, 1)
+        .build();
+    let err = Err(MarginError::MarketNotListed(1));
+    assert_eq!(user.get_liabilities_value(&group), err);
+    assert_eq!(user.get_margin_c_ratio(&group), err);
+    assert_eq!(user.is_bankrupt(&group), err);
+}
+
+#[test]
+fn empty_position_in_delisted_market_is_ignored() {
+    let group = group_with_market(40);
+    let user = user_builder(&group)
+        .deposit(QUOTE_TOKEN_IDX, 100)
+        .deposit(1, 0)
+        .build();
+    assert_eq!(user.get_assets_value(&group), Ok(Number::from(100_u64)));
+    assert_eq!(user.is_bankrupt(&group), Ok(false));
+}
+
+proptest! {
+    #[test]
+    fn c_ratio_increases_with_collateral_price(
+        deposits in 1..1_000_000_000_000_u64,
+        borrows in 1..1_000_000_000_000_u64,
+        price in 1..1_000_000_u64,
+        increase in 0..1_000_000_u64,
+    ) {
+        let low = group_with_market(price);
+        let high = group_with_market(price + increase);
+        let user = user_builder(&low)
+            .deposit(SOL_IDX, deposits)
+            .borrow(QUOTE_TOKEN_IDX, borrows)
+            .build();
+        prop_assert!(user.get_margin_c_ratio(&low).unwrap() <= user.get_margin_c_ratio(&high).unwrap());
+    }
+
+    #[test]
+    fn c_ratio_decreases_with_liability_price(
+        deposits in 1..1_000_000_000_000_u64,
+        borrows in 1..1_000_000_000_000_u64,
+        price in 1..1_000_000_u64,
+        increase in 0..1_000_000_u64,
+    ) {
+        let low = group_with_market(price);
+        let high = group_with_market(price + increase);
+        let user = user_builder(&low)
+            .deposit(QUOTE_TOKEN_IDX, deposits)
+            .borrow(SOL_IDX, borrows)
+            .build();
+        prop_assert!(user.get_margin_c_ratio(&low).unwrap() >= user.get_margin_c_ratio(&high).unwrap());
+    }
+
+    #[test]
+    fn zero_liabilities_ratio_is_always_max(
+        quote_deposits in 0..1_000_000_000_000_u64,
+        deposits in 0..1_000_000_000_000_u64,
+        price in 1..1_000_000_u64,
+    ) {
+        let group = group_with_market(price);
+        let user = user_builder(&group)
+            .deposit(QUOTE_TOKEN_IDX, quote_deposits)
+            .deposit(SOL_IDX, deposits)
+            .build();
+        prop_assert_eq!(user.get_margin_c_ratio(&group).unwrap(), Number::MAX);
+        prop_assert!(!user.is_bankrupt(&group).unwrap());
+    }
+
+    #[test]
+    fn c_ratio_is_invariant_under_index_scaling(
+        deposits in 1..1_000_000_000_000_u64,
+        borrows in 1..1_000_000_000_000_u64,
+        index_percent in 100..1_000_u16,
+    ) {
+        let index = Number::from_percent(index_percent);
+        let group = CypherGroupBuilder::new().build();
+        let scaled = CypherGroupBuilder::new()
+            .token_indices(QUOTE_TOKEN_IDX, index, index)
+            .build();
+        let user = user_builder(&group)
+            .deposit(QUOTE_TOKEN_IDX, deposits)
+            .borrow(QUOTE_TOKEN_IDX, borrows)
+            .build();
+        prop_assert_eq!(
+            user.get_margin_c_ratio(&group).unwrap(),
+            user.get_margin_c_ratio(&scaled).unwrap()
+        );
+    }
+}
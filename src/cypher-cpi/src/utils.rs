@@ -4,22 +4,60 @@ use {
     anchor_lang::{prelude::*, ZeroCopy},
     anchor_spl::dex,
     arrayref::array_ref,
-    bytemuck::{bytes_of, from_bytes, Pod},
+    bytemuck::{bytes_of, try_from_bytes},
+    jet_proto_math::Number,
 };
 
+/// converts the number into a `u64`, rounding down, `None` if it does not fit
+pub fn checked_as_u64(number: Number) -> Option<u64> {
+    if number > Number::from(u64::MAX) {
+        return None;
+    }
+    Some(number.as_u64(0))
+}
+
+/// converts the number into a `u64`, rounding up, `None` if it does not fit
+pub fn checked_as_u64_ceil(number: Number) -> Option<u64> {
+    if number > Number::from(u64::MAX) {
+        return None;
+    }
+    Some(number.as_u64_ceil(0))
+}
+
+/// borrows the zero copy account from the account data without copying it
+///
+/// fails if the account data does not start with the account's discriminator or is too short for the account
+pub fn get_zero_copy_account_ref<T: ZeroCopy + Owner>(account_data: &[u8]) -> Result<&T> {
+    if account_data.len() < 8 {
+        return Err(ErrorCode::AccountDiscriminatorNotFound.into());
+    }
+    let disc_bytes = array_ref![account_data, 0, 8];
+    if disc_bytes != &T::discriminator() {
+        return Err(ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    account_data
+        .get(8..std::mem::size_of::<T>() + 8)
+        .and_then(|data| try_from_bytes::<T>(data).ok())
+        .ok_or_else(|| ErrorCode::AccountDidNotDeserialize.into())
+}
+
+/// copies the zero copy account from the account data onto the heap, which is too heavy for on-chain programs
+#[cfg(feature = "client")]
 pub fn get_zero_copy_account<T: ZeroCopy + Owner>(account_data: &[u8]) -> Box<T> {
     let disc_bytes = array_ref![account_data, 0, 8];
     assert_eq!(disc_bytes, &T::discriminator());
-    Box::new(*from_bytes::<T>(
+    Box::new(*bytemuck::from_bytes::<T>(
         &account_data[8..std::mem::size_of::<T>() + 8],
     ))
 }
 
-pub fn parse_dex_account<T: Pod>(data: &[u8]) -> T {
+/// copies a serum account from its account data, without the serum padding
+#[cfg(feature = "client")]
+pub fn parse_dex_account<T: bytemuck::Pod>(data: &[u8]) -> T {
     let data_len = data.len() - 12;
     let (_, rest) = data.split_at(5);
     let (mid, _) = rest.split_at(data_len);
-    *from_bytes(mid)
+    *bytemuck::from_bytes(mid)
 }
 
 pub fn gen_dex_vault_signer_key(nonce: u64, dex_market_pk: &Pubkey) -> Pubkey {
@@ -0,0 +1,472 @@
+use {
+    crate::{
+        constants::*, CypherGroup, CypherMarket, CypherToken, CypherUser, MarginResult,
+        OpenOrdersInfo, PriceHistory, UserPosition,
+    },
+    anchor_lang::prelude::*,
+    jet_proto_math::Number,
+    serde::{Deserialize, Serialize},
+};
+
+fn pubkey_string(pubkey: &Pubkey) -> String {
+    pubkey.to_string()
+}
+
+fn number_string(number: Number) -> String {
+    number.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CypherGroupConfigView {
+    pub margin_init_ratio: u16,
+    pub margin_maint_ratio: u16,
+    pub margin_partial_ratio: u16,
+    pub liquidator_bonus_bps: u16,
+    pub liquidation_insurance_fee_bps: u16,
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+    pub fee_percentage_for_protocol: u8,
+    pub fee_percentage_for_insurance_fund: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CypherGroupView {
+    pub self_address: String,
+    pub admin: String,
+    pub vault_signer: String,
+    pub insurance_fund: u64,
+    pub protocol_fee: u64,
+    pub config: CypherGroupConfigView,
+    /// the listed tokens, empty slots are omitted
+    pub tokens: Vec<CypherTokenView>,
+    /// the listed markets, empty slots are omitted
+    pub markets: Vec<CypherMarketView>,
+}
+
+impl From<&CypherGroup> for CypherGroupView {
+    fn from(group: &CypherGroup) -> Self {
+        let config = group.config;
+        Self {
+            self_address: pubkey_string(&group.self_address),
+            admin: pubkey_string(&group.admin),
+            vault_signer: pubkey_string(&group.vault_signer),
+            insurance_fund: group.insurance_fund,
+            protocol_fee: group.protocol_fee,
+            config: CypherGroupConfigView {
+                margin_init_ratio: config.margin_init_ratio,
+                margin_maint_ratio: config.margin_maint_ratio,
+                margin_partial_ratio: config.margin_partial_ratio,
+                liquidator_bonus_bps: config.liquidator_bonus_bps,
+                liquidation_insurance_fee_bps: config.liquidation_insurance_fee_bps,
+                maker_fee_bps: config.maker_fee_bps,
+                taker_fee_bps: config.taker_fee_bps,
+                fee_percentage_for_protocol: config.fee_percentage_for_protocol,
+                fee_percentage_for_insurance_fund: config.fee_percentage_for_insurance_fund,
+            },
+            tokens: (0..TOKENS_MAX_CNT)
+                .filter_map(|idx| {
+                    group
+                        .get_cypher_token(idx)
+                        .map(|token| CypherTokenView::new(idx, token))
+                })
+                .collect(),
+            markets: (0..MARKETS_MAX_CNT)
+                .filter_map(|idx| {
+                    group
+                        .get_cypher_market(idx)
+                        .map(|market| CypherMarketView::new(idx, market))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CypherTokenView {
+    pub index: usize,
+    pub mint: String,
+    pub vault: String,
+    pub decimals: u8,
+    pub base_deposits: String,
+    pub base_borrows: String,
+    pub deposit_index: String,
+    pub borrow_index: String,
+    /// the base deposits adjusted for the deposit index
+    pub total_deposits: String,
+    /// the base borrows adjusted for the borrow index
+    pub total_borrows: String,
+    /// the total borrows over the total deposits
+    pub utilization: String,
+    pub index_updated_at: u64,
+    pub accum_borrows: u64,
+    pub accum_repays: u64,
+    pub optimal_util: u16,
+    pub optimal_apr: u16,
+    pub max_apr: u16,
+}
+
+impl CypherTokenView {
+    pub fn new(index: usize, token: &CypherToken) -> Self {
+        let config = token.config;
+        Self {
+            index,
+            mint: pubkey_string(&token.mint),
+            vault: pubkey_string(&token.vault),
+            decimals: token.decimals(),
+            base_deposits: number_string(token.base_deposits()),
+            base_borrows: number_string(token.base_borrows()),
+            deposit_index: number_string(token.deposit_index()),
+            borrow_index: number_string(token.borrow_index()),
+            total_deposits: number_string(token.total_deposits()),
+            total_borrows: number_string(token.total_borrows()),
+            utilization: number_string(token.utilization()),
+            index_updated_at: token.index_updated_at,
+            accum_borrows: token.accum_borrows,
+            accum_repays: token.accum_repays,
+            optimal_util: config.optimal_util,
+            optimal_apr: config.optimal_apr,
+            max_apr: config.max_apr,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CypherMarketView {
+    pub index: usize,
+    pub dex_market: String,
+    pub price_history: String,
+    pub pyth_products: String,
+    pub oracle_price: u64,
+    pub oracle_cached_slot: u64,
+    pub market_price: u64,
+    pub positions_count: u64,
+    pub listed_at: u64,
+    pub expires_at: u64,
+    pub market_type: String,
+    pub is_executed: bool,
+}
+
+impl CypherMarketView {
+    pub fn new(index: usize, market: &CypherMarket) -> Self {
+        let oracle_price = market.oracle_price;
+        Self {
+            index,
+            dex_market: pubkey_string(&market.dex_market),
+            price_history: pubkey_string(&market.price_history),
+            pyth_products: pubkey_string(&market.pyth_products),
+            oracle_price: oracle_price.price,
+            oracle_cached_slot: oracle_price.cached_slot,
+            market_price: market.market_price,
+            positions_count: market.positions_count,
+            listed_at: market.listed_at,
+            expires_at: market.expires_at,
+            market_type: market.market_type_name().to_string(),
+            is_executed: market.is_executed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenOrdersInfoView {
+    pub coin_total: u64,
+    pub coin_free: u64,
+    pub pc_total: u64,
+    pub pc_free: u64,
+    pub referrer_rebates_accrued: u64,
+}
+
+impl From<&OpenOrdersInfo> for OpenOrdersInfoView {
+    fn from(oo_info: &OpenOrdersInfo) -> Self {
+        Self {
+            coin_total: oo_info.coin_total,
+            coin_free: oo_info.coin_free,
+            pc_total: oo_info.pc_total,
+            pc_free: oo_info.pc_free,
+            referrer_rebates_accrued: oo_info.referrer_rebates_accrued,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserPositionView {
+    pub market_idx: u8,
+    pub base_deposits: String,
+    pub base_borrows: String,
+    /// the open orders info, omitted if the open orders account is not open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oo_info: Option<OpenOrdersInfoView>,
+}
+
+impl From<&UserPosition> for UserPositionView {
+    fn from(position: &UserPosition) -> Self {
+        let oo_info = &position.oo_info;
+        Self {
+            market_idx: position.market_idx,
+            base_deposits: number_string(position.base_deposits()),
+            base_borrows: number_string(position.base_borrows()),
+            oo_info: if oo_info.is_account_open {
+                Some(oo_info.into())
+            } else {
+                None
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CypherUserView {
+    pub cypher_group: String,
+    pub owner: String,
+    /// the delegate, omitted if none is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delegate: Option<String>,
+    /// the quote position, adjusted for the quote token's deposit and borrow indices
+    pub quote_deposits: String,
+    pub quote_borrows: String,
+    /// the positions in markets, unused positions are omitted
+    pub positions: Vec<UserPositionView>,
+    pub assets_value: String,
+    pub liabilities_value: String,
+    /// the margin c-ratio, omitted if the user has no liabilities
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_ratio: Option<String>,
+}
+
+impl CypherUserView {
+    pub fn new(user: &CypherUser, group: &CypherGroup) -> MarginResult<Self> {
+        let (c_ratio, assets_value, liabs_value) = user.get_margin_c_ratio_components(group)?;
+        let (quote_deposits, quote_borrows) = match (
+            user.get_position(QUOTE_TOKEN_IDX),
+            group.get_cypher_token(QUOTE_TOKEN_IDX),
+        ) {
+            (Some(position), Some(token)) => (
+                position.total_deposits(token),
+                position.total_borrows(token),
+            ),
+            _ => (Number::ZERO, Number::ZERO),
+        };
+        Ok(Self {
+            cypher_group: pubkey_string(&user.cypher_group),
+            owner: pubkey_string(&user.user_signer),
+            delegate: if user.delegate == Pubkey::default() {
+                None
+            } else {
+                Some(pubkey_string(&user.delegate))
+            },
+            quote_deposits: number_string(quote_deposits),
+            quote_borrows: number_string(quote_borrows),
+            positions: user.iter_positions().map(|p| p.into()).collect(),
+            assets_value: number_string(assets_value),
+            liabilities_value: number_string(liabs_value),
+            c_ratio: if liabs_value == Number::ZERO {
+                None
+            } else {
+                Some(number_string(c_ratio))
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceWithTsView {
+    pub price: u64,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceHistoryView {
+    pub cypher_group: String,
+    pub c_asset_mint: String,
+    pub longer_time_horizon: u64,
+    pub shorter_time_horizon: u64,
+    pub price_collection_tick: u64,
+    pub longer_head: u16,
+    pub shorter_head: u16,
+    pub tail: u16,
+    /// the recorded prices, unused slots are omitted
+    pub data: Vec<PriceWithTsView>,
+}
+
+impl From<&PriceHistory> for PriceHistoryView {
+    fn from(price_history: &PriceHistory) -> Self {
+        let config = price_history.config;
+        Self {
+            cypher_group: pubkey_string(&price_history.cypher_group),
+            c_asset_mint: pubkey_string(&price_history.c_asset_mint),
+            longer_time_horizon: config.longer_time_horizon,
+            shorter_time_horizon: config.shorter_time_horizon,
+            price_collection_tick: config.price_collection_tick,
+            longer_head: price_history.longer_head,
+            shorter_head: price_history.shorter_head,
+            tail: price_history.tail,
+            data: price_history
+                .iter_prices()
+                .map(|p| PriceWithTsView {
+                    price: p.price,
+                    timestamp: p.timestamp,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::test_utils::*, serde_json::json};
+
+    #[test]
+    fn serializes_groups_with_listed_slots_only() {
+        let mint = Pubkey::new_unique();
+        let group = CypherGroupBuilder::new()
+            .fund(1_000, 20)
+            .market(0, mint, 9, 40)
+            .token_balances(
+                0,
+                Number::from(2_500_000_000_u64),
+                Number::from(500_000_000_u64),
+            )
+            .build();
+        let (token, quote, market) = (
+            group.get_cypher_token(0).unwrap(),
+            group.get_cypher_token(QUOTE_TOKEN_IDX).unwrap(),
+            group.get_cypher_market(0).unwrap(),
+        );
+        assert_eq!(
+            serde_json::to_value(CypherGroupView::from(&*group)).unwrap(),
+            json!({
+                "self_address": group.self_address.to_string(),
+                "admin": group.admin.to_string(),
+                "vault_signer": group.vault_signer.to_string(),
+                "insurance_fund": 1_000,
+                "protocol_fee": 20,
+                "config": {
+                    "margin_init_ratio": 150,
+                    "margin_maint_ratio": 110,
+                    "margin_partial_ratio": 130,
+                    "liquidator_bonus_bps": 500,
+                    "liquidation_insurance_fee_bps": 100,
+                    "maker_fee_bps": 0,
+                    "taker_fee_bps": 10,
+                    "fee_percentage_for_protocol": 0,
+                    "fee_percentage_for_insurance_fund": 0,
+                },
+                "tokens": [
+                    {
+                        "index": 0,
+                        "mint": mint.to_string(),
+                        "vault": token.vault.to_string(),
+                        "decimals": 9,
+                        "base_deposits": "2500000000.0",
+                        "base_borrows": "500000000.0",
+                        "deposit_index": "1.0",
+                        "borrow_index": "1.0",
+                        "total_deposits": "2500000000.0",
+                        "total_borrows": "500000000.0",
+                        "utilization": "0.2",
+                        "index_updated_at": 0,
+                        "accum_borrows": 0,
+                        "accum_repays": 0,
+                        "optimal_util": 0,
+                        "optimal_apr": 0,
+                        "max_apr": 0,
+                    },
+                    {
+                        "index": QUOTE_TOKEN_IDX,
+                        "mint": crate::quote_mint::ID.to_string(),
+                        "vault": quote.vault.to_string(),
+                        "decimals": 6,
+                        "base_deposits": "0.0",
+                        "base_borrows": "0.0",
+                        "deposit_index": "1.0",
+                        "borrow_index": "1.0",
+                        "total_deposits": "0.0",
+                        "total_borrows": "0.0",
+                        "utilization": "0.0",
+                        "index_updated_at": 0,
+                        "accum_borrows": 0,
+                        "accum_repays": 0,
+                        "optimal_util": 0,
+                        "optimal_apr": 0,
+                        "max_apr": 0,
+                    },
+                ],
+                "markets": [{
+                    "index": 0,
+                    "dex_market": market.dex_market.to_string(),
+                    "price_history": market.price_history.to_string(),
+                    "pyth_products": market.pyth_products.to_string(),
+                    "oracle_price": 40,
+                    "oracle_cached_slot": 0,
+                    "market_price": 40,
+                    "positions_count": 0,
+                    "listed_at": 0,
+                    "expires_at": 0,
+                    "market_type": "PairFuture",
+                    "is_executed": false,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_users_without_empty_fields() {
+        let group = CypherGroupBuilder::new()
+            .market(0, Pubkey::new_unique(), 6, 40)
+            .build();
+        let owner = Pubkey::new_unique();
+        let user = CypherUserBuilder::new(group.self_address, owner)
+            .deposit(QUOTE_TOKEN_IDX, 100_000_000)
+            .borrow(0, 2_000_000)
+            .build();
+        assert_eq!(
+            serde_json::to_value(CypherUserView::new(&user, &group).unwrap()).unwrap(),
+            json!({
+                "cypher_group": group.self_address.to_string(),
+                "owner": owner.to_string(),
+                "quote_deposits": "100000000.0",
+                "quote_borrows": "0.0",
+                "positions": [{
+                    "market_idx": 0,
+                    "base_deposits": "0.0",
+                    "base_borrows": "2000000.0",
+                }],
+                "assets_value": "100000000.0",
+                "liabilities_value": "80000000.0",
+                "c_ratio": "1.25",
+            })
+        );
+
+        let delegate = Pubkey::new_unique();
+        let user = CypherUserBuilder::new(group.self_address, owner)
+            .delegate(delegate)
+            .build();
+        assert_eq!(
+            serde_json::to_value(CypherUserView::new(&user, &group).unwrap()).unwrap(),
+            json!({
+                "cypher_group": group.self_address.to_string(),
+                "owner": owner.to_string(),
+                "delegate": delegate.to_string(),
+                "quote_deposits": "0.0",
+                "quote_borrows": "0.0",
+                "positions": [],
+                "assets_value": "0.0",
+                "liabilities_value": "0.0",
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_open_orders_of_positions() {
+        let user = CypherUserBuilder::new(Pubkey::new_unique(), Pubkey::new_unique())
+            .deposit(0, 2_500_000)
+            .open_orders(0, 100, 5_000_000)
+            .build();
+        let json = serde_json::to_string(&UserPositionView::from(&user.positions[0])).unwrap();
+        assert_eq!(
+            json,
+            r#"{"market_idx":0,"base_deposits":"2500000.0","base_borrows":"0.0","oo_info":{"coin_total":100,"coin_free":100,"pc_total":5000000,"pc_free":5000000,"referrer_rebates_accrued":0}}"#
+        );
+        let view: UserPositionView = serde_json::from_str(&json).unwrap();
+        assert_eq!(view, UserPositionView::from(&user.positions[0]));
+    }
+}